@@ -0,0 +1,88 @@
+//! Conditions for the `check` subcommand and the exit codes they map to.
+//!
+//! Exit codes are a bitmask, so a run that finds both uncommitted changes
+//! and unpushed commits exits with 3.
+use crate::{BranchStat, RepoError};
+use anyhow::{anyhow, Result};
+use std::str::FromStr;
+
+pub const DIRTY: i32 = 1;
+pub const UNPUSHED: i32 = 2;
+pub const BEHIND: i32 = 4;
+pub const ERROR: i32 = 8;
+
+/// Something that makes a repository fail the check
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Condition {
+    /// Modified, staged or conflicted files, or an unfinished operation
+    Dirty,
+    Untracked,
+    /// A local branch ahead of its upstream
+    Unpushed,
+    /// Any stash entry, which is work that exists nowhere else
+    Stash,
+    /// A local branch behind its upstream
+    Behind,
+    /// The repository could not be inspected
+    Error,
+}
+
+/// Conditions checked when none are given
+pub const DEFAULT: [Condition; 5] = [
+    Condition::Dirty,
+    Condition::Untracked,
+    Condition::Unpushed,
+    Condition::Behind,
+    Condition::Error,
+];
+
+impl FromStr for Condition {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Condition> {
+        match s {
+            "dirty" => Ok(Condition::Dirty),
+            "untracked" => Ok(Condition::Untracked),
+            "unpushed" => Ok(Condition::Unpushed),
+            "stash" => Ok(Condition::Stash),
+            "behind" => Ok(Condition::Behind),
+            "error" => Ok(Condition::Error),
+            _ => Err(anyhow!("Unknown condition '{}'", s)),
+        }
+    }
+}
+
+impl Condition {
+    /// The exit code bit set when this condition holds
+    pub fn code(self) -> i32 {
+        match self {
+            Condition::Dirty | Condition::Untracked => DIRTY,
+            Condition::Unpushed | Condition::Stash => UNPUSHED,
+            Condition::Behind => BEHIND,
+            Condition::Error => ERROR,
+        }
+    }
+
+    pub fn holds(self, result: &Result<BranchStat, RepoError>) -> bool {
+        let stat = match result {
+            Ok(stat) => stat,
+            Err(_) => return self == Condition::Error,
+        };
+        match self {
+            Condition::Dirty => stat.is_dirty(),
+            Condition::Untracked => stat.untracked > 0,
+            Condition::Unpushed => stat.tracking.iter().any(|x| x.ahead > 0),
+            Condition::Stash => !stat.stashes.is_empty(),
+            Condition::Behind => stat.tracking.iter().any(|x| x.behind > 0),
+            Condition::Error => false,
+        }
+    }
+}
+
+/// The exit code bits for every condition that holds for `result`
+pub fn exit_code(result: &Result<BranchStat, RepoError>, conditions: &[Condition]) -> i32 {
+    conditions
+        .iter()
+        .filter(|x| x.holds(result))
+        .fold(0, |code, x| code | x.code())
+}
@@ -0,0 +1,1111 @@
+//! Command-line parsing, help text and shell completions.
+//!
+//! Every flag is described once in `FLAGS`, which drives parsing, `help`
+//! and the generated completion scripts.
+use anyhow::{anyhow, Result};
+use git_branchstat::check::{self, Condition};
+use git_branchstat::config::Config;
+use git_branchstat::filter::{Filter, Filters};
+use git_branchstat::group::{GroupBy, SortKey};
+use git_branchstat::{Backend, Collector, Collectors, RenderOptions, Subprocess};
+use std::path::Path;
+use std::str::FromStr;
+
+pub const NAME: &str = "git-branchstat";
+pub const VERSION: &str = "0.1.0";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Command {
+    #[default]
+    Status,
+    Branches,
+    Check,
+    Config,
+    Version,
+    Help,
+    Completions,
+}
+
+const COMMANDS: [(Command, &str, &str); 7] = [
+    (
+        Command::Status,
+        "status",
+        "Summarise each repository (the default)",
+    ),
+    (
+        Command::Branches,
+        "branches",
+        "List what each repository has checked out",
+    ),
+    (
+        Command::Check,
+        "check",
+        "Exit non-zero if any repository needs attention",
+    ),
+    (
+        Command::Config,
+        "config",
+        "Show the settings from config files and the environment",
+    ),
+    (Command::Version, "version", "Print the version"),
+    (Command::Help, "help", "Show help for a command"),
+    (
+        Command::Completions,
+        "completions",
+        "Print a completion script for bash, zsh or fish",
+    ),
+];
+
+// Commands that look at repositories, and so take paths
+const INSPECTING: &[Command] = &[Command::Status, Command::Branches, Command::Check];
+const ALL: &[Command] = &[
+    Command::Status,
+    Command::Branches,
+    Command::Check,
+    Command::Config,
+    Command::Version,
+    Command::Help,
+    Command::Completions,
+];
+
+impl Command {
+    fn name(self) -> &'static str {
+        COMMANDS.iter().find(|x| x.0 == self).map_or("", |x| x.1)
+    }
+
+    fn named(name: &str) -> Option<Command> {
+        COMMANDS.iter().find(|x| x.1 == name).map(|x| x.0)
+    }
+}
+
+struct Flag {
+    long: &'static str,
+    short: Option<char>,
+    /// Placeholder for the flag's value, if it takes one
+    value: Option<&'static str>,
+    /// Values offered by completions
+    choices: &'static [&'static str],
+    commands: &'static [Command],
+    help: &'static str,
+}
+
+const FLAGS: &[Flag] = &[
+    Flag {
+        long: "format",
+        short: None,
+        value: Some("FORMAT"),
+        choices: &["text", "json", "ndjson"],
+        commands: INSPECTING,
+        help: "Output format: text, json or ndjson",
+    },
+    Flag {
+        long: "color",
+        short: None,
+        value: Some("WHEN"),
+        choices: &["auto", "always", "never"],
+        commands: INSPECTING,
+        help: "Color text output: auto, always or never",
+    },
+    Flag {
+        long: "theme",
+        short: None,
+        value: Some("NAME"),
+        choices: &["default", "bright", "mono"],
+        commands: &[Command::Status, Command::Check],
+        help: "Colors to use: default, bright or mono",
+    },
+    Flag {
+        long: "symbols",
+        short: None,
+        value: Some("SET"),
+        choices: &["unicode", "ascii", "nerd"],
+        commands: &[Command::Status, Command::Check],
+        help: "Markers to use: unicode, ascii or nerd",
+    },
+    Flag {
+        long: "path",
+        short: Some('C'),
+        value: Some("DIR"),
+        choices: &[],
+        commands: INSPECTING,
+        help: "Inspect repositories at or beneath DIR",
+    },
+    Flag {
+        long: "paths-from",
+        short: None,
+        value: Some("FILE"),
+        choices: &[],
+        commands: INSPECTING,
+        help: "Read paths from FILE, one per line or NUL-separated",
+    },
+    Flag {
+        long: "manifest",
+        short: None,
+        value: Some("FILE"),
+        choices: &[],
+        commands: INSPECTING,
+        help: "Inspect the repositories listed in FILE",
+    },
+    Flag {
+        long: "tag",
+        short: None,
+        value: Some("TAG"),
+        choices: &[],
+        commands: INSPECTING,
+        help: "Only manifest entries with TAG, may be repeated",
+    },
+    Flag {
+        long: "wide",
+        short: None,
+        value: None,
+        choices: &[],
+        commands: INSPECTING,
+        help: "Never shorten columns to fit the terminal",
+    },
+    Flag {
+        long: "names",
+        short: None,
+        value: Some("STYLE"),
+        choices: &["dir", "parent"],
+        commands: INSPECTING,
+        help: "Label repositories by dir or parent/dir",
+    },
+    Flag {
+        long: "compact",
+        short: None,
+        value: None,
+        choices: &[],
+        commands: &[Command::Status, Command::Check],
+        help: "Show totals instead of breakdowns",
+    },
+    Flag {
+        long: "native",
+        short: None,
+        value: None,
+        choices: &[],
+        commands: &[Command::Status, Command::Check],
+        help: "Read repositories in-process instead of running git",
+    },
+    Flag {
+        long: "legacy-collectors",
+        short: None,
+        value: None,
+        choices: &[],
+        commands: &[Command::Status, Command::Check],
+        help: "Use separate git commands instead of git status",
+    },
+    Flag {
+        long: "dirty",
+        short: None,
+        value: None,
+        choices: &[],
+        commands: &[Command::Status],
+        help: "Show repositories with changes, conflicts or an operation in progress",
+    },
+    Flag {
+        long: "ahead",
+        short: None,
+        value: None,
+        choices: &[],
+        commands: &[Command::Status],
+        help: "Show repositories with unpushed commits",
+    },
+    Flag {
+        long: "behind",
+        short: None,
+        value: None,
+        choices: &[],
+        commands: &[Command::Status],
+        help: "Show repositories behind their upstream",
+    },
+    Flag {
+        long: "untracked",
+        short: None,
+        value: None,
+        choices: &[],
+        commands: &[Command::Status],
+        help: "Show repositories with untracked files",
+    },
+    Flag {
+        long: "stash",
+        short: None,
+        value: None,
+        choices: &[],
+        commands: &[Command::Status],
+        help: "Show repositories with stashes",
+    },
+    Flag {
+        long: "conflicts",
+        short: None,
+        value: None,
+        choices: &[],
+        commands: &[Command::Status],
+        help: "Show repositories with merge conflicts",
+    },
+    Flag {
+        long: "no-upstream",
+        short: None,
+        value: None,
+        choices: &[],
+        commands: &[Command::Status],
+        help: "Show repositories whose branch has no upstream",
+    },
+    Flag {
+        long: "all",
+        short: None,
+        value: None,
+        choices: &[],
+        commands: &[Command::Status],
+        help: "Show every repository, including clean ones",
+    },
+    Flag {
+        long: "match",
+        short: None,
+        value: Some("MODE"),
+        choices: &["any", "all"],
+        commands: &[Command::Status],
+        help: "Show repositories matching any or all filters",
+    },
+    Flag {
+        long: "sort",
+        short: None,
+        value: Some("KEY"),
+        choices: &[
+            "name",
+            "path",
+            "dirty",
+            "ahead",
+            "behind",
+            "time",
+            "attention",
+        ],
+        commands: &[Command::Status, Command::Check],
+        help: "Order by name, path, dirty, ahead, behind, time or attention",
+    },
+    Flag {
+        long: "group-by",
+        short: None,
+        value: Some("KEY"),
+        choices: &["parent", "host", "owner", "label"],
+        commands: &[Command::Status, Command::Check],
+        help: "Group by parent directory, remote host or owner, or label",
+    },
+    Flag {
+        long: "summary-only",
+        short: None,
+        value: None,
+        choices: &[],
+        commands: &[Command::Status],
+        help: "Print only the totals across all repositories",
+    },
+    Flag {
+        long: "strict",
+        short: None,
+        value: None,
+        choices: &[],
+        commands: &[Command::Status, Command::Branches],
+        help: "Exit with 1 if any repository could not be inspected",
+    },
+    Flag {
+        long: "fail-on",
+        short: None,
+        value: Some("LIST"),
+        choices: &["dirty", "untracked", "unpushed", "stash", "behind", "error"],
+        commands: &[Command::Check],
+        help: "Comma-separated conditions that fail the check",
+    },
+    Flag {
+        long: "quiet",
+        short: Some('q'),
+        value: None,
+        choices: &[],
+        commands: &[Command::Check],
+        help: "Print nothing, only set the exit code",
+    },
+    Flag {
+        long: "help",
+        short: Some('h'),
+        value: None,
+        choices: &[],
+        commands: ALL,
+        help: "Show help",
+    },
+    Flag {
+        long: "version",
+        short: Some('V'),
+        value: None,
+        choices: &[],
+        commands: ALL,
+        help: "Print the version",
+    },
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Format {
+    #[default]
+    Text,
+    Json,
+    Ndjson,
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Format> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            "ndjson" => Ok(Format::Ndjson),
+            _ => Err(anyhow!("Unknown format '{}'", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl FromStr for Shell {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Shell> {
+        match s {
+            "bash" => Ok(Shell::Bash),
+            "zsh" => Ok(Shell::Zsh),
+            "fish" => Ok(Shell::Fish),
+            _ => Err(anyhow!("Unknown shell '{}', expected bash, zsh or fish", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    pub command: Command,
+    /// The command `help` was asked about
+    pub topic: Option<Command>,
+    /// The shell `completions` was asked for
+    pub shell: Option<Shell>,
+    pub format: Format,
+    pub collector: Collector,
+    pub native: bool,
+    /// The optional parts of the state to gather
+    pub collectors: Collectors,
+    pub render: RenderOptions,
+    /// Print text output at full width, even past the terminal's edge
+    pub wide: bool,
+    /// Which repositories `status` shows
+    pub filters: Filters,
+    pub sort: SortKey,
+    /// Print results in groups, each with a heading and subtotal
+    pub group_by: Option<GroupBy>,
+    /// Print the totals footer without the repositories above it
+    pub summary_only: bool,
+    /// Exit with an error if any repository could not be inspected
+    pub strict: bool,
+    /// Conditions that fail `check`
+    pub fail_on: Vec<Condition>,
+    /// Print nothing, for `check`
+    pub quiet: bool,
+    pub dirs: Vec<String>,
+    /// Files listing more paths, with `-` for stdin
+    pub lists: Vec<String>,
+    /// A manifest listing the repositories to inspect
+    pub manifest: Option<String>,
+    /// Manifest tags to select entries by
+    pub tags: Vec<String>,
+    /// Flags given on the command line, which win over the config
+    pub given: Vec<&'static str>,
+}
+
+impl Options {
+    pub fn backend(&self) -> Box<dyn Backend> {
+        #[cfg(feature = "native")]
+        {
+            if self.native {
+                return Box::new(git_branchstat::Native {
+                    collectors: self.collectors,
+                });
+            }
+        }
+        Box::new(Subprocess {
+            collector: self.collector,
+            collectors: self.collectors,
+        })
+    }
+
+    /// Take settings from `config` that no flag has given
+    pub fn apply(&mut self, config: &Config) -> Result<()> {
+        let given = self.given.clone();
+        let unset = |flags: &[&str]| !flags.iter().any(|x| given.contains(x));
+        if !config.format.is_default() && unset(&["format"]) {
+            self.format = config.format.parse()?;
+        }
+        if !config.theme.is_default() && unset(&["theme"]) {
+            self.render.theme = config.theme.parse()?;
+        }
+        if !config.symbols.is_default() && unset(&["symbols"]) {
+            self.render.symbols = config.symbols.parse()?;
+        }
+        if !config.collector.is_default() && unset(&["native", "legacy-collectors"]) {
+            match config.collector.value.as_str() {
+                "native" if cfg!(feature = "native") => self.native = true,
+                "native" => {
+                    return Err(anyhow!(
+                        "The native collector needs the 'native' feature (from {})",
+                        config.collector.source
+                    ))
+                }
+                _ => self.collector = config.collector.parse()?,
+            }
+        }
+        // Collectors that only some options use are turned on for them,
+        // unless the config turns them off
+        let json = self.format != Format::Text && !self.summary_only;
+        let group = |by: &[GroupBy]| self.group_by.is_some_and(|x| by.contains(&x));
+        let needed = Collectors {
+            last_commit: json || self.sort == SortKey::Time,
+            remote: json || self.manifest.is_some() || group(&[GroupBy::Host, GroupBy::Owner]),
+            label: json || group(&[GroupBy::Label]),
+            ..Collectors::default()
+        };
+        self.collectors = config.collectors(needed);
+        Ok(())
+    }
+}
+
+/// Parse the arguments after the program name. The command may appear
+/// anywhere before the first path, so `--format json check` works too.
+pub fn parse_args(args: &[String]) -> Result<Options> {
+    let mut command = None;
+    let mut flags: Vec<(&Flag, Option<String>)> = Vec::new();
+    let mut positional: Vec<String> = Vec::new();
+    // Whether everything in `positional` followed a `--`
+    let mut literal = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let (flag, inline) = if arg == "--" {
+            literal = positional.is_empty();
+            positional.extend(args.by_ref().cloned());
+            break;
+        } else if let Some(long) = arg.strip_prefix("--") {
+            let (name, inline) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (long, None),
+            };
+            let flag = FLAGS
+                .iter()
+                .find(|x| x.long == name)
+                .ok_or_else(|| unknown_flag(&format!("--{}", name)))?;
+            (flag, inline)
+        } else if arg.starts_with('-') && arg != "-" {
+            let mut chars = arg.chars().skip(1);
+            let flag = match (chars.next(), chars.next()) {
+                (Some(c), None) => FLAGS.iter().find(|x| x.short == Some(c)),
+                _ => None,
+            };
+            (flag.ok_or_else(|| unknown_flag(arg))?, None)
+        } else {
+            match Command::named(arg) {
+                Some(named) if command.is_none() && positional.is_empty() => command = Some(named),
+                _ => positional.push(arg.to_string()),
+            }
+            continue;
+        };
+        let value = match (flag.value, inline) {
+            (Some(_), Some(value)) => Some(value),
+            (Some(_), None) => Some(
+                args.next()
+                    .ok_or_else(|| anyhow!("{} needs a value", arg))?
+                    .to_string(),
+            ),
+            (None, Some(_)) => return Err(anyhow!("--{} does not take a value", flag.long)),
+            (None, None) => None,
+        };
+        flags.push((flag, value));
+    }
+
+    let mut opts = Options {
+        command: command.unwrap_or_default(),
+        ..Default::default()
+    };
+    if opts.command == Command::Check {
+        opts.fail_on = check::DEFAULT.to_vec();
+    }
+    for (flag, value) in flags {
+        if !flag.commands.contains(&opts.command) {
+            return Err(anyhow!(
+                "--{} is not valid for {}",
+                flag.long,
+                opts.command.name()
+            ));
+        }
+        let value = value.unwrap_or_default();
+        opts.given.push(flag.long);
+        match flag.long {
+            "format" => opts.format = value.parse()?,
+            "color" => opts.render.color = value.parse()?,
+            "theme" => opts.render.theme = value.parse()?,
+            "symbols" => opts.render.symbols = value.parse()?,
+            "path" => opts.dirs.push(value),
+            "paths-from" => opts.lists.push(value),
+            "manifest" => opts.manifest = Some(value),
+            "tag" => opts.tags.push(value),
+            "names" => opts.render.naming = value.parse()?,
+            "compact" => opts.render.compact = true,
+            "wide" => opts.wide = true,
+            "native" => {
+                if !cfg!(feature = "native") {
+                    return Err(anyhow!("--native needs the 'native' feature"));
+                }
+                opts.native = true;
+            }
+            "legacy-collectors" => opts.collector = Collector::Legacy,
+            "strict" => opts.strict = true,
+            "dirty" => opts.filters.filters.push(Filter::Dirty),
+            "ahead" => opts.filters.filters.push(Filter::Ahead),
+            "behind" => opts.filters.filters.push(Filter::Behind),
+            "untracked" => opts.filters.filters.push(Filter::Untracked),
+            "stash" => opts.filters.filters.push(Filter::Stash),
+            "conflicts" => opts.filters.filters.push(Filter::Conflicts),
+            "no-upstream" => opts.filters.filters.push(Filter::NoUpstream),
+            "all" => opts.filters.all = true,
+            "match" => opts.filters.mode = value.parse()?,
+            "sort" => opts.sort = value.parse()?,
+            "group-by" => opts.group_by = Some(value.parse()?),
+            "summary-only" => opts.summary_only = true,
+            "fail-on" => {
+                opts.fail_on = value
+                    .split(',')
+                    .map(|x| x.trim().parse())
+                    .collect::<Result<_>>()?
+            }
+            "quiet" => opts.quiet = true,
+            "help" => {
+                opts.topic = Some(opts.command);
+                opts.command = Command::Help;
+            }
+            "version" => opts.command = Command::Version,
+            _ => unreachable!("every flag in FLAGS is handled"),
+        }
+    }
+
+    if !opts.tags.is_empty() && opts.manifest.is_none() {
+        return Err(anyhow!("--tag needs --manifest"));
+    }
+    match opts.command {
+        Command::Help if opts.topic.is_some() => {}
+        Command::Help => {
+            opts.topic = match positional.as_slice() {
+                [] => None,
+                [name] => Some(Command::named(name).ok_or_else(|| unknown_command(name))?),
+                _ => return Err(anyhow!("help takes one command")),
+            }
+        }
+        Command::Completions => match positional.as_slice() {
+            [shell] => opts.shell = Some(shell.parse()?),
+            _ => return Err(anyhow!("completions needs a shell: bash, zsh or fish")),
+        },
+        Command::Config => match positional.split_first() {
+            Some((action, paths)) if action == "show" => opts.dirs = paths.to_vec(),
+            Some((action, _)) => return Err(anyhow!("Unknown config action '{}'", action)),
+            None => return Err(anyhow!("config needs an action: show")),
+        },
+        Command::Version if !positional.is_empty() => {
+            return Err(anyhow!("version takes no arguments"))
+        }
+        Command::Version => {}
+        _ => {
+            // A bare word that nearly names a command and is not a path is
+            // more likely a typo. Exact names after a command or `--` are
+            // meant as paths.
+            if let Some(first) = positional.first().filter(|_| !literal) {
+                let path = Path::new(first);
+                if !path.exists()
+                    && path.components().count() == 1
+                    && Command::named(first).is_none()
+                    && closest(first, COMMANDS.iter().map(|x| x.1)).is_some()
+                {
+                    return Err(unknown_command(first));
+                }
+            }
+            for path in positional {
+                if path == "-" {
+                    opts.lists.push(path);
+                } else {
+                    opts.dirs.push(path);
+                }
+            }
+        }
+    }
+    Ok(opts)
+}
+
+fn unknown_flag(arg: &str) -> anyhow::Error {
+    let name = arg.trim_start_matches('-');
+    match closest(name, FLAGS.iter().map(|x| x.long)) {
+        Some(similar) => anyhow!(
+            "Unknown argument '{}', did you mean '--{}'?\nRun '{} help' for usage.",
+            arg,
+            similar,
+            NAME
+        ),
+        None => anyhow!("Unknown argument '{}'\nRun '{} help' for usage.", arg, NAME),
+    }
+}
+
+fn unknown_command(name: &str) -> anyhow::Error {
+    match closest(name, COMMANDS.iter().map(|x| x.1)) {
+        Some(similar) => anyhow!(
+            "Unknown command '{}', did you mean '{}'?\nRun '{} help' for usage.",
+            name,
+            similar,
+            NAME
+        ),
+        None => anyhow!("Unknown command '{}'\nRun '{} help' for usage.", name, NAME),
+    }
+}
+
+// The candidate within two edits of `name`, if any
+fn closest<'a>(name: &str, candidates: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    candidates
+        .map(|x| (distance(name, x), x))
+        .filter(|(d, _)| *d <= 2)
+        .min()
+        .map(|(_, x)| x)
+}
+
+// Levenshtein distance
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = (diagonal + usize::from(ca != cb))
+                .min(row[j] + 1)
+                .min(above + 1);
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+/// Usage for the whole program, or for one command
+pub fn help(topic: Option<Command>) -> String {
+    let mut out = String::new();
+    match topic {
+        None => {
+            out.push_str(&format!(
+                "{} {}\nSummarise the state of many git repositories\n\n",
+                NAME, VERSION
+            ));
+            out.push_str(&format!(
+                "Usage: {} [COMMAND] [OPTIONS] [PATH]...\n\nCommands:\n",
+                NAME
+            ));
+            for (_, name, help) in &COMMANDS {
+                out.push_str(&format!("  {:14}{}\n", name, help));
+            }
+            out.push_str(
+                "\nPaths are searched for repositories. With none, the configured roots\n\
+                 are searched, or else the repository containing the current directory\n\
+                 is used; '-' reads paths from stdin. See 'help config' for settings.\n\
+                 With --manifest, the repositories it lists are inspected instead, any\n\
+                 whose origin or default branch differs from its entry is flagged, and\n\
+                 any others found beneath the paths are reported as unlisted.\n",
+            );
+            out.push_str("\nOptions:\n");
+            push_flags(&mut out, Command::Status);
+            out.push_str(&format!(
+                "\nRun '{} help COMMAND' for the options of a command.\n",
+                NAME
+            ));
+        }
+        Some(command) => {
+            let (_, name, help) = COMMANDS.iter().find(|x| x.0 == command).unwrap();
+            out.push_str(&format!("{}\n\nUsage: {} {}", help, NAME, name));
+            match command {
+                Command::Help => out.push_str(" [COMMAND]\n"),
+                Command::Completions => out.push_str(" <bash|zsh|fish>\n"),
+                Command::Version => out.push('\n'),
+                Command::Config => out.push_str(" show [PATH]...\n"),
+                _ => out.push_str(" [OPTIONS] [PATH]...\n"),
+            }
+            if command == Command::Status {
+                out.push_str(
+                    "\nRepositories with nothing to report are hidden unless --all is given.\n\
+                     Filters such as --dirty and --ahead show only the repositories they\n\
+                     match; --match decides whether one filter is enough or all must match.\n\
+                     Scanning more than one repository ends with totals across all of them,\n\
+                     shown or not; --summary-only prints only those.\n",
+                );
+            }
+            if matches!(command, Command::Status | Command::Check) {
+                out.push_str(
+                    "\n--sort puts the most dirty, ahead, behind, recent or in need of\n\
+                     attention first. --group-by adds a heading and subtotal per group in\n\
+                     text output; JSON output is only ordered by group.\n",
+                );
+            }
+            if command == Command::Config {
+                out.push_str(
+                    "\nPrints the merged settings and where each came from. Settings are\n\
+                     read from $XDG_CONFIG_HOME/git-branchstat/config.toml, then a\n\
+                     .branchstat.toml in each PATH (or each configured root), then\n\
+                     GIT_BRANCHSTAT_* environment variables; flags override them all.\n\
+                     Settings: roots, exclude, collector, format, theme, symbols, labels,\n\
+                     collectors.\n",
+                );
+            }
+            if command == Command::Check {
+                out.push_str(&format!(
+                    "\nThe exit code adds up {} for uncommitted changes, {} for unpushed\n\
+                     commits, {} for branches behind their upstream and {} for errors.\n\
+                     Conditions: dirty, untracked, unpushed, stash, behind, error\n\
+                     (default: all but stash).\n",
+                    check::DIRTY,
+                    check::UNPUSHED,
+                    check::BEHIND,
+                    check::ERROR
+                ));
+            }
+            out.push_str("\nOptions:\n");
+            push_flags(&mut out, command);
+        }
+    }
+    out
+}
+
+fn push_flags(out: &mut String, command: Command) {
+    for flag in FLAGS.iter().filter(|x| x.commands.contains(&command)) {
+        let short = match flag.short {
+            Some(c) => format!("-{}, ", c),
+            None => "    ".to_string(),
+        };
+        let long = match flag.value {
+            Some(value) => format!("--{} {}", flag.long, value),
+            None => format!("--{}", flag.long),
+        };
+        out.push_str(&format!("  {}{:24}{}\n", short, long, flag.help));
+    }
+}
+
+/// A completion script for `shell`
+pub fn completions(shell: Shell) -> String {
+    match shell {
+        Shell::Bash => bash(),
+        Shell::Zsh => zsh(),
+        Shell::Fish => fish(),
+    }
+}
+
+fn command_names() -> Vec<&'static str> {
+    COMMANDS.iter().map(|x| x.1).collect()
+}
+
+fn bash() -> String {
+    let mut values = String::new();
+    for flag in FLAGS.iter().filter(|x| x.value.is_some()) {
+        let mut names = format!("--{}", flag.long);
+        if let Some(c) = flag.short {
+            names.push_str(&format!("|-{}", c));
+        }
+        let reply = match flag.value {
+            Some("DIR") => "compgen -d -- \"$cur\"".to_string(),
+            Some("FILE") => "compgen -f -- \"$cur\"".to_string(),
+            _ => format!("compgen -W \"{}\" -- \"$cur\"", flag.choices.join(" ")),
+        };
+        values.push_str(&format!(
+            "        {}) COMPREPLY=($({})); return ;;\n",
+            names, reply
+        ));
+    }
+    let mut words: Vec<String> = FLAGS.iter().map(|x| format!("--{}", x.long)).collect();
+    words.extend(
+        FLAGS
+            .iter()
+            .filter_map(|x| x.short)
+            .map(|c| format!("-{}", c)),
+    );
+    format!(
+        r#"# bash completion for {name}
+_git_branchstat() {{
+    local cur="${{COMP_WORDS[COMP_CWORD]}}"
+    local prev="${{COMP_WORDS[COMP_CWORD-1]}}"
+    case "$prev" in
+{values}        completions) COMPREPLY=($(compgen -W "bash zsh fish" -- "$cur")); return ;;
+        help) COMPREPLY=($(compgen -W "{commands}" -- "$cur")); return ;;
+        config) COMPREPLY=($(compgen -W "show" -- "$cur")); return ;;
+    esac
+    if [[ "$cur" == -* ]]; then
+        COMPREPLY=($(compgen -W "{flags}" -- "$cur"))
+    elif [[ $COMP_CWORD -eq 1 ]]; then
+        COMPREPLY=($(compgen -W "{commands}" -- "$cur") $(compgen -d -- "$cur"))
+    else
+        COMPREPLY=($(compgen -d -- "$cur"))
+    fi
+}}
+complete -F _git_branchstat {name}
+"#,
+        name = NAME,
+        values = values,
+        commands = command_names().join(" "),
+        flags = words.join(" ")
+    )
+}
+
+// Escape a description for a single-quoted zsh spec. Colons separate
+// fields in `_describe` entries, brackets end `_arguments` descriptions.
+fn zsh_escape(s: &str, special: &[char]) -> String {
+    let mut out = String::new();
+    for c in s.chars() {
+        match c {
+            '\'' => out.push_str("'\\''"),
+            c if special.contains(&c) => {
+                out.push('\\');
+                out.push(c);
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn zsh() -> String {
+    let mut specs = String::new();
+    for flag in FLAGS {
+        // Long-only flags take `--flag=value` or `--flag value`; a short
+        // flag's value always follows in the next word
+        let names = match (flag.short, flag.value) {
+            (Some(c), _) => format!("'(-{c} --{l})'{{-{c},--{l}}}'", c = c, l = flag.long),
+            (None, Some(_)) => format!("'--{}=", flag.long),
+            (None, None) => format!("'--{}", flag.long),
+        };
+        let value = match flag.value {
+            Some("DIR") => format!(":{}:_files -/", flag.long),
+            Some("FILE") => format!(":{}:_files", flag.long),
+            Some(_) => format!(":{}:({})", flag.long, flag.choices.join(" ")),
+            None => String::new(),
+        };
+        specs.push_str(&format!(
+            "        {}[{}]{}' \\\n",
+            names,
+            zsh_escape(flag.help, &['[', ']']),
+            value
+        ));
+    }
+    let commands: Vec<String> = COMMANDS
+        .iter()
+        .map(|(_, name, help)| format!("        '{}:{}'", name, zsh_escape(help, &[':'])))
+        .collect();
+    format!(
+        r#"#compdef {name}
+
+_git_branchstat() {{
+    local -a commands
+    commands=(
+{commands}
+    )
+    _arguments -s \
+{specs}        '1: :->first' \
+        '*:path:_files -/'
+    if [[ $state == first ]]; then
+        _describe command commands
+        _files -/
+    fi
+}}
+
+_git_branchstat "$@"
+"#,
+        name = NAME,
+        commands = commands.join("\n"),
+        specs = specs
+    )
+}
+
+fn fish() -> String {
+    let mut out = format!("# fish completion for {}\ncomplete -c {} -f\n", NAME, NAME);
+    let names = command_names();
+    for (_, name, help) in &COMMANDS {
+        out.push_str(&format!(
+            "complete -c {} -n __fish_use_subcommand -a {} -d '{}'\n",
+            NAME, name, help
+        ));
+    }
+    out.push_str(&format!(
+        "complete -c {} -n '__fish_seen_subcommand_from completions' -a 'bash zsh fish'\n",
+        NAME
+    ));
+    out.push_str(&format!(
+        "complete -c {} -n '__fish_seen_subcommand_from config' -a show\n",
+        NAME
+    ));
+    out.push_str(&format!(
+        "complete -c {} -n '__fish_seen_subcommand_from help' -a '{}'\n",
+        NAME,
+        names.join(" ")
+    ));
+    out.push_str(&format!(
+        "complete -c {} -n 'not __fish_seen_subcommand_from version help completions' -a '(__fish_complete_directories)'\n",
+        NAME
+    ));
+    for flag in FLAGS {
+        let mut line = format!("complete -c {}", NAME);
+        // Flags for the default command apply until another one is named
+        if flag.commands != ALL {
+            let applies = flag.commands.contains(&Command::Status);
+            let others: Vec<&str> = COMMANDS
+                .iter()
+                .filter(|x| flag.commands.contains(&x.0) != applies)
+                .map(|x| x.1)
+                .collect();
+            let negate = if applies { "not " } else { "" };
+            line.push_str(&format!(
+                " -n '{}__fish_seen_subcommand_from {}'",
+                negate,
+                others.join(" ")
+            ));
+        }
+        if let Some(c) = flag.short {
+            line.push_str(&format!(" -s {}", c));
+        }
+        line.push_str(&format!(" -l {}", flag.long));
+        match flag.value {
+            Some("DIR") => line.push_str(" -x -a '(__fish_complete_directories)'"),
+            Some("FILE") => line.push_str(" -r -F"),
+            Some(_) => line.push_str(&format!(" -x -a '{}'", flag.choices.join(" "))),
+            None => {}
+        }
+        line.push_str(&format!(" -d '{}'\n", flag.help));
+        out.push_str(&line);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git_branchstat::config::{Setting, Source};
+
+    fn parse(args: &[&str]) -> Result<Options> {
+        let args: Vec<String> = args.iter().map(|x| x.to_string()).collect();
+        parse_args(&args)
+    }
+
+    #[test]
+    fn flags_before_the_command() {
+        let opts = parse(&["--format", "json", "check"]).unwrap();
+        assert_eq!(opts.command, Command::Check);
+        assert_eq!(opts.format, Format::Json);
+        assert_eq!(opts.fail_on, check::DEFAULT.to_vec());
+    }
+
+    #[test]
+    fn inline_values() {
+        let opts = parse(&["--format=ndjson", "--sort=time", "dir"]).unwrap();
+        assert_eq!(opts.format, Format::Ndjson);
+        assert_eq!(opts.sort, SortKey::Time);
+        assert_eq!(opts.dirs, vec!["dir"]);
+        assert_eq!(opts.given, vec!["format", "sort"]);
+        let err = parse(&["--wide=yes"]).unwrap_err();
+        assert_eq!(err.to_string(), "--wide does not take a value");
+    }
+
+    #[test]
+    fn unknown_flags_suggest_the_closest() {
+        let err = parse(&["--fromat", "json"]).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("Unknown argument '--fromat', did you mean '--format'?"));
+        let err = parse(&["--nothing-like-it"]).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("Unknown argument '--nothing-like-it'\n"));
+        let err = parse(&["--quiet"]).unwrap_err();
+        assert_eq!(err.to_string(), "--quiet is not valid for status");
+    }
+
+    #[test]
+    fn collectors_follow_the_options() {
+        let collectors = |args: &[&str]| {
+            let mut opts = parse(args).unwrap();
+            opts.apply(&Config::default()).unwrap();
+            let c = opts.collectors;
+            (c.last_commit, c.remote, c.label)
+        };
+        assert_eq!(collectors(&[]), (false, false, false));
+        assert_eq!(collectors(&["--sort", "time"]), (true, false, false));
+        assert_eq!(collectors(&["--group-by", "owner"]), (false, true, false));
+        assert_eq!(collectors(&["--group-by", "label"]), (false, false, true));
+        assert_eq!(
+            collectors(&["--manifest", "list.toml"]),
+            (false, true, false)
+        );
+        assert_eq!(collectors(&["--format", "ndjson"]), (true, true, true));
+        let mut config = Config::default();
+        config.collectors.push(Setting {
+            value: ("last_commit".to_string(), false),
+            source: Source::Default,
+        });
+        let mut opts = parse(&["--sort", "time"]).unwrap();
+        opts.apply(&config).unwrap();
+        assert!(!opts.collectors.last_commit);
+    }
+
+    #[test]
+    fn paths_named_like_commands() {
+        // Only the first word can be the command
+        let opts = parse(&["status", "check"]).unwrap();
+        assert_eq!(opts.command, Command::Status);
+        assert_eq!(opts.dirs, vec!["check"]);
+        let opts = parse(&["--", "check"]).unwrap();
+        assert_eq!(opts.command, Command::Status);
+        assert_eq!(opts.dirs, vec!["check"]);
+        let opts = parse(&["./check", "-"]).unwrap();
+        assert_eq!(opts.dirs, vec!["./check"]);
+        assert_eq!(opts.lists, vec!["-"]);
+        // A missing path that is nearly a command is probably a typo
+        let err = parse(&["chekc"]).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("Unknown command 'chekc', did you mean 'check'?"));
+    }
+
+    #[test]
+    fn flags_are_checked_against_the_command() {
+        let opts = parse(&["check", "--fail-on", "dirty,behind", "-q"]).unwrap();
+        assert_eq!(opts.fail_on, vec![Condition::Dirty, Condition::Behind]);
+        assert!(opts.quiet);
+        assert!(parse(&["--tag", "x"]).is_err());
+        let opts = parse(&["help", "check"]).unwrap();
+        assert_eq!(opts.topic, Some(Command::Check));
+        let opts = parse(&["check", "--help"]).unwrap();
+        assert_eq!(
+            (opts.command, opts.topic),
+            (Command::Help, Some(Command::Check))
+        );
+    }
+
+    #[test]
+    fn completions_offer_every_flag() {
+        for shell in &[Shell::Bash, Shell::Zsh, Shell::Fish] {
+            let script = completions(*shell);
+            for flag in FLAGS {
+                assert!(
+                    script.contains(flag.long),
+                    "{:?} lacks --{}",
+                    shell,
+                    flag.long
+                );
+            }
+            for name in command_names() {
+                assert!(script.contains(name), "{:?} lacks {}", shell, name);
+            }
+        }
+    }
+}
@@ -0,0 +1,449 @@
+//! Settings from config files and the environment.
+//!
+//! Sources are read in order, each overriding the ones before it:
+//!
+//! 1. `$XDG_CONFIG_HOME/git-branchstat/config.toml`, or
+//!    `~/.config/git-branchstat/config.toml`
+//! 2. `.branchstat.toml` in each scanned root, in the order given
+//! 3. `GIT_BRANCHSTAT_ROOTS`, `GIT_BRANCHSTAT_EXCLUDE` (both separated like
+//!    `PATH`), `GIT_BRANCHSTAT_COLLECTOR`, `GIT_BRANCHSTAT_FORMAT`,
+//!    `GIT_BRANCHSTAT_THEME` and `GIT_BRANCHSTAT_SYMBOLS`
+//!
+//! Command-line flags override all of them. `exclude`, `labels` and
+//! `collectors` collect entries from every source instead of replacing them.
+//!
+//! ```toml
+//! roots = ["~/code", "~/work"]     # scanned when no paths are given
+//! exclude = ["node_modules", "~/code/archive/*"]
+//! collector = "porcelain"          # porcelain, legacy or native
+//! format = "text"                  # text, json or ndjson
+//! theme = "default"                # default, bright or mono
+//! symbols = "unicode"              # unicode, ascii or nerd
+//!
+//! [labels]                         # for --group-by label
+//! "~/work/*" = "work"
+//!
+//! [collectors]                     # what to read from each repository
+//! stash = false                    # stash entries
+//! diffstat = false                 # line counts of changes
+//! last_commit = true               # for --sort time
+//! remote = true                    # for --group-by host and owner
+//! label = true                     # branchstat.label in git config
+//! ```
+//!
+//! `stash` and `diffstat` are on unless turned off. The others are off
+//! unless turned on, or needed by `--sort time`, `--group-by`, `--manifest`
+//! or JSON output; a collector turned off here stays off even then.
+//!
+//! Patterns without a `/` match the name of any directory, like
+//! `.gitignore` patterns. Others match the whole path, relative to the
+//! directory of the file they appear in. A `branchstat.label` set in a
+//! repository's git config wins over `labels`.
+use crate::glob::wildmatch;
+use crate::table::{Cell, Table};
+use crate::toml::{self, Value};
+use crate::{Collectors, RenderOptions};
+use anyhow::{anyhow, Result};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Where a setting came from
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    Default,
+    File(PathBuf),
+    Env(&'static str),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::Default => write!(f, "default"),
+            Source::File(path) => write!(f, "{}", path.display()),
+            Source::Env(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// A value and where it came from
+#[derive(Debug, Clone, PartialEq)]
+pub struct Setting<T> {
+    pub value: T,
+    pub source: Source,
+}
+
+impl<T> Setting<T> {
+    fn default(value: T) -> Setting<T> {
+        Setting {
+            value,
+            source: Source::Default,
+        }
+    }
+
+    pub fn is_default(&self) -> bool {
+        self.source == Source::Default
+    }
+}
+
+impl Setting<String> {
+    /// Parse the value, naming its source if it is invalid
+    pub fn parse<T: FromStr<Err = anyhow::Error>>(&self) -> Result<T> {
+        self.value
+            .parse()
+            .map_err(|e| anyhow!("{} (from {})", e, self.source))
+    }
+}
+
+/// The merged settings
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    /// Directories scanned when no paths are given
+    pub roots: Setting<Vec<PathBuf>>,
+    /// Directories never descended into while looking for repositories
+    pub exclude: Vec<Setting<String>>,
+    pub collector: Setting<String>,
+    pub format: Setting<String>,
+    pub theme: Setting<String>,
+    pub symbols: Setting<String>,
+    /// Labels by repository path pattern, later entries winning
+    pub labels: Vec<Setting<(String, String)>>,
+    /// Collectors turned on or off, later entries winning
+    pub collectors: Vec<Setting<(String, bool)>>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            roots: Setting::default(Vec::new()),
+            exclude: Vec::new(),
+            collector: Setting::default("porcelain".to_string()),
+            format: Setting::default("text".to_string()),
+            theme: Setting::default("default".to_string()),
+            symbols: Setting::default("unicode".to_string()),
+            labels: Vec::new(),
+            collectors: Vec::new(),
+        }
+    }
+}
+
+const ROOT_FILE: &str = ".branchstat.toml";
+
+impl Config {
+    /// Read every source. The root files read are those in `roots`, or in
+    /// the configured roots if it is empty.
+    pub fn load(roots: &[PathBuf]) -> Result<Config> {
+        let mut config = Config::default();
+        if let Some(file) = global_path().filter(|x| x.is_file()) {
+            config.read_file(&file, true)?;
+        }
+        if let Some(roots) = env_list("GIT_BRANCHSTAT_ROOTS") {
+            config.roots = Setting {
+                value: roots.iter().filter_map(|x| expand_home(x)).collect(),
+                source: Source::Env("GIT_BRANCHSTAT_ROOTS"),
+            };
+        }
+        let roots = if roots.is_empty() {
+            config.roots.value.clone()
+        } else {
+            roots.to_vec()
+        };
+        for root in roots {
+            let file = root.join(ROOT_FILE);
+            if file.is_file() {
+                config.read_file(&file, false)?;
+            }
+        }
+        config.read_env()?;
+        Ok(config)
+    }
+
+    fn read_file(&mut self, file: &Path, global: bool) -> Result<()> {
+        let error = |e: anyhow::Error| anyhow!("{}: {}", file.display(), e);
+        let text = std::fs::read_to_string(file).map_err(|e| error(e.into()))?;
+        let table = toml::parse(&text).map_err(error)?;
+        let dir = file.parent().unwrap_or(Path::new(""));
+        let source = Source::File(file.to_path_buf());
+        let setting = |value: String| Setting {
+            value,
+            source: source.clone(),
+        };
+        for (key, value) in table {
+            match (key.as_str(), value) {
+                ("roots", _) if !global => {
+                    return Err(error(anyhow!("roots can only be set globally")))
+                }
+                ("roots", Value::Array(items)) => {
+                    let roots = strings(&key, items).map_err(error)?;
+                    self.roots = Setting {
+                        value: roots.iter().map(|x| resolve(dir, x)).collect(),
+                        source: source.clone(),
+                    };
+                }
+                ("exclude", Value::Array(items)) => {
+                    for pattern in strings(&key, items).map_err(error)? {
+                        self.exclude.push(setting(resolve_pattern(dir, &pattern)));
+                    }
+                }
+                ("collector", Value::String(s)) => self.collector = setting(s),
+                ("format", Value::String(s)) => self.format = setting(s),
+                ("theme", Value::String(s)) => self.theme = setting(s),
+                ("symbols", Value::String(s)) => self.symbols = setting(s),
+                ("labels", Value::Table(labels)) => {
+                    for (pattern, label) in labels {
+                        let label = match label {
+                            Value::String(label) => label,
+                            other => {
+                                return Err(error(anyhow!(
+                                    "labels.\"{}\" should be a string, not {}",
+                                    pattern,
+                                    other.type_name()
+                                )))
+                            }
+                        };
+                        let pattern = resolve_pattern(dir, &pattern);
+                        self.labels.push(Setting {
+                            value: (pattern, label),
+                            source: source.clone(),
+                        });
+                    }
+                }
+                ("collectors", Value::Table(collectors)) => {
+                    for (name, on) in collectors {
+                        Collectors::default().get_mut(&name).map_err(error)?;
+                        let on = match on {
+                            Value::Boolean(on) => on,
+                            other => {
+                                return Err(error(anyhow!(
+                                    "collectors.{} should be a boolean, not {}",
+                                    name,
+                                    other.type_name()
+                                )))
+                            }
+                        };
+                        self.collectors.push(Setting {
+                            value: (name, on),
+                            source: source.clone(),
+                        });
+                    }
+                }
+                (
+                    "roots" | "exclude" | "collector" | "format" | "theme" | "symbols" | "labels"
+                    | "collectors",
+                    other,
+                ) => {
+                    return Err(error(anyhow!(
+                        "{} should not be {}",
+                        key,
+                        other.type_name()
+                    )))
+                }
+                _ => return Err(error(anyhow!("unknown setting '{}'", key))),
+            }
+        }
+        Ok(())
+    }
+
+    fn read_env(&mut self) -> Result<()> {
+        let cwd = std::env::current_dir()?;
+        if let Some(patterns) = env_list("GIT_BRANCHSTAT_EXCLUDE") {
+            for pattern in patterns {
+                self.exclude.push(Setting {
+                    value: resolve_pattern(&cwd, &pattern),
+                    source: Source::Env("GIT_BRANCHSTAT_EXCLUDE"),
+                });
+            }
+        }
+        let vars = vec![
+            ("GIT_BRANCHSTAT_COLLECTOR", &mut self.collector),
+            ("GIT_BRANCHSTAT_FORMAT", &mut self.format),
+            ("GIT_BRANCHSTAT_THEME", &mut self.theme),
+            ("GIT_BRANCHSTAT_SYMBOLS", &mut self.symbols),
+        ];
+        for (name, setting) in vars {
+            if let Some(value) = std::env::var(name).ok().filter(|x| !x.is_empty()) {
+                *setting = Setting {
+                    value,
+                    source: Source::Env(name),
+                };
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the directory at `path` should be skipped
+    pub fn is_excluded(&self, path: &Path) -> bool {
+        self.exclude.iter().any(|x| matches(&x.value, path))
+    }
+
+    /// The label for the repository at `path`, if any pattern matches
+    pub fn label(&self, path: &Path) -> Option<&str> {
+        self.labels
+            .iter()
+            .rev()
+            .find(|x| matches(&x.value.0, path))
+            .map(|x| x.value.1.as_str())
+    }
+
+    /// Which collectors are on once every source is applied over `collectors`
+    pub fn collectors(&self, mut collectors: Collectors) -> Collectors {
+        for setting in &self.collectors {
+            if let Ok(on) = collectors.get_mut(&setting.value.0) {
+                *on = setting.value.1;
+            }
+        }
+        collectors
+    }
+
+    /// The merged settings as TOML, with the source of each value
+    pub fn show(&self) -> String {
+        let mut table = Table::default();
+        let mut row = |text: String, source: Option<&Source>| {
+            let source = source.map_or(String::new(), |x| format!("# {}", x));
+            table.push(vec![Cell::new(text, ""), Cell::new(source, "")]);
+        };
+        let roots: Vec<String> = self
+            .roots
+            .value
+            .iter()
+            .map(|x| quote(&x.to_string_lossy()))
+            .collect();
+        row(
+            format!("roots = [{}]", roots.join(", ")),
+            Some(&self.roots.source),
+        );
+        if self.exclude.is_empty() {
+            row("exclude = []".to_string(), Some(&Source::Default));
+        } else {
+            row("exclude = [".to_string(), None);
+            for pattern in &self.exclude {
+                row(
+                    format!("    {},", quote(&pattern.value)),
+                    Some(&pattern.source),
+                );
+            }
+            row("]".to_string(), None);
+        }
+        for (key, setting) in &[
+            ("collector", &self.collector),
+            ("format", &self.format),
+            ("theme", &self.theme),
+            ("symbols", &self.symbols),
+        ] {
+            row(
+                format!("{} = {}", key, quote(&setting.value)),
+                Some(&setting.source),
+            );
+        }
+        table.push_heading(Cell::new("[labels]".to_string(), ""));
+        for label in &self.labels {
+            let (pattern, name) = &label.value;
+            table.push(vec![
+                Cell::new(format!("{} = {}", quote(pattern), quote(name)), ""),
+                Cell::new(format!("# {}", label.source), ""),
+            ]);
+        }
+        table.push_heading(Cell::new("[collectors]".to_string(), ""));
+        let mut collectors = Collectors::default();
+        for name in &Collectors::NAMES {
+            let setting = self.collectors.iter().rev().find(|x| x.value.0 == *name);
+            let on = collectors.get_mut(name).expect("every name is known");
+            let source = match setting {
+                Some(setting) => {
+                    *on = setting.value.1;
+                    &setting.source
+                }
+                None => &Source::Default,
+            };
+            table.push(vec![
+                Cell::new(format!("{} = {}", name, on), ""),
+                Cell::new(format!("# {}", source), ""),
+            ]);
+        }
+        table.render(&RenderOptions::default(), None)
+    }
+}
+
+fn global_path() -> Option<PathBuf> {
+    xdg_config_home().map(|x| x.join("git-branchstat/config.toml"))
+}
+
+// The strings in a TOML array
+fn strings(key: &str, items: Vec<Value>) -> Result<Vec<String>> {
+    items
+        .into_iter()
+        .map(|x| match x {
+            Value::String(s) => Ok(s),
+            other => Err(anyhow!(
+                "{} should hold strings, not {}",
+                key,
+                other.type_name()
+            )),
+        })
+        .collect()
+}
+
+// A list from an environment variable, separated like `PATH`
+fn env_list(name: &str) -> Option<Vec<String>> {
+    let value = std::env::var_os(name).filter(|x| !x.is_empty())?;
+    Some(
+        std::env::split_paths(&value)
+            .map(|x| x.to_string_lossy().into_owned())
+            .filter(|x| !x.is_empty())
+            .collect(),
+    )
+}
+
+// A path relative to `dir`, unless it is absolute or starts with `~/`
+fn resolve(dir: &Path, path: &str) -> PathBuf {
+    match expand_home(path) {
+        Some(path) if path.is_absolute() => path,
+        _ => dir.join(path),
+    }
+}
+
+// Patterns with a `/` are resolved like paths, others match any directory
+fn resolve_pattern(dir: &Path, pattern: &str) -> String {
+    if !pattern.contains('/') || pattern == "." {
+        return match pattern {
+            "." => dir.to_string_lossy().into_owned(),
+            _ => pattern.to_string(),
+        };
+    }
+    resolve(dir, pattern.trim_start_matches("./"))
+        .to_string_lossy()
+        .trim_end_matches('/')
+        .to_string()
+}
+
+fn matches(pattern: &str, path: &Path) -> bool {
+    let path = path.to_string_lossy();
+    if pattern.contains('/') {
+        wildmatch(pattern.as_bytes(), path.as_bytes())
+    } else {
+        let name = path.rsplit('/').next().unwrap_or(&path);
+        wildmatch(pattern.as_bytes(), name.as_bytes())
+    }
+}
+
+fn quote(s: &str) -> String {
+    format!("{:?}", s)
+}
+
+pub(crate) fn home() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(PathBuf::from)
+}
+
+pub(crate) fn xdg_config_home() -> Option<PathBuf> {
+    match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+        _ => home().map(|x| x.join(".config")),
+    }
+}
+
+pub(crate) fn expand_home(path: &str) -> Option<PathBuf> {
+    match path.strip_prefix("~/") {
+        Some(rest) => home().map(|x| x.join(rest)),
+        None => Some(PathBuf::from(path)),
+    }
+}
@@ -0,0 +1,122 @@
+//! Errors reported for a single repository.
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Why a repository could not be inspected
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// The `git` binary could not be started
+    GitNotFound,
+    /// A path given to scan does not exist
+    NotFound,
+    /// A path given to scan is a file
+    NotADirectory,
+    NotARepository,
+    /// git refuses to work in a repository owned by another user until it
+    /// is listed in `safe.directory`
+    DubiousOwnership,
+    /// A git command was killed after running this long
+    Timeout(Duration),
+    /// A git command failed for any other reason
+    Git {
+        command: String,
+        code: Option<i32>,
+        stderr: String,
+    },
+    /// Output that could not be understood
+    Parse(String),
+    /// Listed in the manifest but not on disk, with the remote to clone
+    Missing(Option<String>),
+}
+
+impl Error {
+    /// A stable identifier for machine-readable output
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::GitNotFound => "git_not_found",
+            Error::NotFound => "not_found",
+            Error::NotADirectory => "not_a_directory",
+            Error::NotARepository => "not_a_repository",
+            Error::DubiousOwnership => "dubious_ownership",
+            Error::Timeout(_) => "timeout",
+            Error::Git { .. } => "git",
+            Error::Parse(_) => "parse",
+            Error::Missing(_) => "missing",
+        }
+    }
+
+    /// Classify a failed git command by its exit code and stderr
+    pub fn from_git(command: &str, code: Option<i32>, stderr: &str) -> Error {
+        if stderr.contains("detected dubious ownership") {
+            Error::DubiousOwnership
+        } else if stderr.contains("not a git repository") {
+            Error::NotARepository
+        } else {
+            Error::Git {
+                command: command.to_string(),
+                code,
+                stderr: stderr.trim().to_string(),
+            }
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::GitNotFound => write!(f, "git not found"),
+            Error::NotFound => write!(f, "does not exist"),
+            Error::NotADirectory => write!(f, "not a directory"),
+            Error::NotARepository => write!(f, "not a git repository"),
+            Error::DubiousOwnership => {
+                write!(f, "dubious ownership, see `git config safe.directory`")
+            }
+            Error::Timeout(limit) => write!(f, "git timed out after {}s", limit.as_secs()),
+            Error::Git {
+                command,
+                code,
+                stderr,
+            } => {
+                match code {
+                    Some(code) => write!(f, "`git {}` exited with {}", command, code)?,
+                    None => write!(f, "`git {}` was killed", command)?,
+                }
+                // The last line of stderr is usually the `fatal:` message
+                match stderr.lines().last() {
+                    Some(line) => write!(f, ": {}", line),
+                    None => Ok(()),
+                }
+            }
+            Error::Parse(what) => write!(f, "could not parse {}", what),
+            Error::Missing(Some(remote)) => write!(f, "missing, clone it from {}", remote),
+            Error::Missing(None) => write!(f, "missing"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// A repository that could not be inspected, and why
+#[derive(Debug)]
+pub struct RepoError {
+    pub path: PathBuf,
+    pub error: anyhow::Error,
+}
+
+impl RepoError {
+    /// The typed error, if the failure was one we recognise
+    pub fn typed(&self) -> Option<&Error> {
+        self.error.downcast_ref::<Error>()
+    }
+
+    pub fn kind(&self) -> &'static str {
+        self.typed().map_or("other", Error::kind)
+    }
+}
+
+impl fmt::Display for RepoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
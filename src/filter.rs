@@ -0,0 +1,111 @@
+//! Choosing which repositories to show.
+use crate::{BranchStat, Head};
+use anyhow::{anyhow, Result};
+use std::str::FromStr;
+
+/// Something a repository can need attention for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Filter {
+    /// `BranchStat::is_dirty`
+    Dirty,
+    /// A local branch ahead of its upstream
+    Ahead,
+    /// A local branch behind its upstream
+    Behind,
+    Untracked,
+    Stash,
+    Conflicts,
+    /// The checked out branch has no upstream configured
+    NoUpstream,
+}
+
+impl Filter {
+    pub fn matches(self, stat: &BranchStat) -> bool {
+        match self {
+            Filter::Dirty => stat.is_dirty(),
+            Filter::Ahead => stat.tracking.iter().any(|x| x.ahead > 0),
+            Filter::Behind => stat.tracking.iter().any(|x| x.behind > 0),
+            Filter::Untracked => stat.untracked > 0,
+            Filter::Stash => !stat.stashes.is_empty(),
+            Filter::Conflicts => stat.conflicts.total() > 0,
+            Filter::NoUpstream => match &stat.head {
+                Head::Branch(name) => !stat
+                    .tracking
+                    .iter()
+                    .any(|x| &x.name == name && x.upstream.is_some()),
+                Head::Unborn(_) => true,
+                Head::Detached { .. } => false,
+            },
+        }
+    }
+}
+
+/// How several filters combine
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Match {
+    /// Show repositories matching any filter
+    #[default]
+    Any,
+    /// Show repositories matching every filter
+    All,
+}
+
+impl FromStr for Match {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Match> {
+        match s {
+            "any" => Ok(Match::Any),
+            "all" => Ok(Match::All),
+            _ => Err(anyhow!("Unknown match mode '{}', expected any or all", s)),
+        }
+    }
+}
+
+/// The repositories to show. With no filters, those that are not clean.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Filters {
+    pub filters: Vec<Filter>,
+    pub mode: Match,
+    /// Show every repository, clean or not
+    pub all: bool,
+}
+
+impl Filters {
+    pub fn matches(&self, stat: &BranchStat) -> bool {
+        if self.all {
+            return true;
+        }
+        if self.filters.is_empty() {
+            return !stat.is_clean();
+        }
+        match self.mode {
+            Match::Any => self.filters.iter().any(|x| x.matches(stat)),
+            Match::All => self.filters.iter().all(|x| x.matches(stat)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::check::Condition;
+    use crate::totals::Totals;
+    use crate::Operation;
+
+    #[test]
+    fn dirty_agrees_with_check_and_totals() {
+        let mut conflicted = BranchStat::default();
+        conflicted.conflicts.add("UU");
+        let merging = BranchStat {
+            operation: Some(Operation::Merge),
+            ..Default::default()
+        };
+        for stat in [conflicted, merging] {
+            assert!(Filter::Dirty.matches(&stat));
+            let result = Ok(stat);
+            assert!(Condition::Dirty.holds(&result));
+            assert_eq!(std::iter::once(&result).collect::<Totals>().dirty, 1);
+        }
+    }
+}
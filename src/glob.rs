@@ -0,0 +1,59 @@
+//! Glob matching in the style of `.gitignore`.
+/// Glob match where `*` and `?` stop at `/` and `**` crosses directories
+pub fn wildmatch(p: &[u8], t: &[u8]) -> bool {
+    match p.first() {
+        None => t.is_empty(),
+        Some(b'*') if p.get(1) == Some(&b'*') => match p[2..].strip_prefix(b"/") {
+            // "**/" matches zero or more leading directories
+            Some(rest) => {
+                wildmatch(rest, t)
+                    || (0..t.len()).any(|i| t[i] == b'/' && wildmatch(rest, &t[i + 1..]))
+            }
+            None => (0..=t.len()).any(|i| wildmatch(&p[2..], &t[i..])),
+        },
+        Some(b'*') => (0..=t.len())
+            .take_while(|&i| i == 0 || t[i - 1] != b'/')
+            .any(|i| wildmatch(&p[1..], &t[i..])),
+        Some(b'?') => !t.is_empty() && t[0] != b'/' && wildmatch(&p[1..], &t[1..]),
+        Some(b'[') => match (t.first(), class_end(p)) {
+            (Some(&c), Some(end)) => {
+                c != b'/' && class_matches(&p[1..end], c) && wildmatch(&p[end + 1..], &t[1..])
+            }
+            (Some(&c), None) => c == b'[' && wildmatch(&p[1..], &t[1..]),
+            (None, _) => false,
+        },
+        Some(b'\\') if p.len() > 1 => t.first() == Some(&p[1]) && wildmatch(&p[2..], &t[1..]),
+        Some(&c) => t.first() == Some(&c) && wildmatch(&p[1..], &t[1..]),
+    }
+}
+
+// Index of the `]` closing the class that starts at `p[0]`
+fn class_end(p: &[u8]) -> Option<usize> {
+    let mut i = 1;
+    if matches!(p.get(i), Some(b'!') | Some(b'^')) {
+        i += 1;
+    }
+    if p.get(i) == Some(&b']') {
+        i += 1;
+    }
+    p.iter().skip(i).position(|&c| c == b']').map(|x| x + i)
+}
+
+fn class_matches(class: &[u8], c: u8) -> bool {
+    let (negated, class) = match class.first() {
+        Some(b'!') | Some(b'^') => (true, &class[1..]),
+        _ => (false, class),
+    };
+    let mut i = 0;
+    let mut found = false;
+    while i < class.len() {
+        if i + 2 < class.len() && class[i + 1] == b'-' {
+            found |= class[i] <= c && c <= class[i + 2];
+            i += 3;
+        } else {
+            found |= class[i] == c;
+            i += 1;
+        }
+    }
+    found != negated
+}
@@ -0,0 +1,194 @@
+//! Ordering results and splitting them into groups.
+use crate::{BranchStat, RepoError};
+use anyhow::{anyhow, Result};
+use std::cmp::Ordering;
+use std::path::Path;
+use std::str::FromStr;
+
+/// What results are ordered by. Counts, recency and attention put the
+/// highest first; ties and repositories that could not be inspected fall
+/// back to the path.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum SortKey {
+    Name,
+    #[default]
+    Path,
+    /// Modified, staged and conflicted files
+    Dirty,
+    /// Unpushed commits across all local branches
+    Ahead,
+    /// Commits to pull across all local branches
+    Behind,
+    /// Time of the last commit on HEAD
+    Time,
+    /// `BranchStat::attention`
+    Attention,
+}
+
+impl FromStr for SortKey {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<SortKey> {
+        match s {
+            "name" => Ok(SortKey::Name),
+            "path" => Ok(SortKey::Path),
+            "dirty" => Ok(SortKey::Dirty),
+            "ahead" => Ok(SortKey::Ahead),
+            "behind" => Ok(SortKey::Behind),
+            "time" => Ok(SortKey::Time),
+            "attention" => Ok(SortKey::Attention),
+            _ => Err(anyhow!("Unknown sort key '{}'", s)),
+        }
+    }
+}
+
+impl SortKey {
+    // The value to sort by, highest first, or `None` to sort last
+    fn value(self, stat: &BranchStat) -> Option<u64> {
+        let n = match self {
+            SortKey::Name | SortKey::Path => return Some(0),
+            SortKey::Dirty => stat.modified + stat.staged.total() + stat.conflicts.total(),
+            SortKey::Ahead => stat.tracking.iter().map(|x| x.ahead).sum(),
+            SortKey::Behind => stat.tracking.iter().map(|x| x.behind).sum(),
+            SortKey::Time => return stat.last_commit,
+            SortKey::Attention => stat.attention(),
+        };
+        Some(n as u64)
+    }
+
+    pub fn compare(
+        self,
+        a: &Result<BranchStat, RepoError>,
+        b: &Result<BranchStat, RepoError>,
+    ) -> Ordering {
+        let (pa, pb) = (result_path(a), result_path(b));
+        let by_value = |x: &Result<BranchStat, RepoError>| match x {
+            Ok(stat) => self.value(stat),
+            Err(_) => None,
+        };
+        let first = match self {
+            SortKey::Name => pa.file_name().cmp(&pb.file_name()),
+            SortKey::Path => Ordering::Equal,
+            // `None` is less than any value, so reversing puts it last
+            _ => by_value(b).cmp(&by_value(a)),
+        };
+        first.then_with(|| pa.cmp(pb))
+    }
+}
+
+/// A heading and the results under it
+pub type Group = (String, Vec<Result<BranchStat, RepoError>>);
+
+/// What results are grouped by
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GroupBy {
+    /// The directory containing the work tree
+    Parent,
+    /// The host of the `origin` remote, such as `github.com`
+    Host,
+    /// The host and owner of the `origin` remote, such as `github.com/me`
+    Owner,
+    /// The `branchstat.label` git config value
+    Label,
+}
+
+impl FromStr for GroupBy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<GroupBy> {
+        match s {
+            "parent" => Ok(GroupBy::Parent),
+            "host" => Ok(GroupBy::Host),
+            "owner" => Ok(GroupBy::Owner),
+            "label" => Ok(GroupBy::Label),
+            _ => Err(anyhow!("Unknown grouping '{}'", s)),
+        }
+    }
+}
+
+impl GroupBy {
+    /// The group `result` belongs in, or `None` if it has nothing to group by
+    pub fn key(self, result: &Result<BranchStat, RepoError>) -> Option<String> {
+        let stat = match result {
+            _ if self == GroupBy::Parent => {
+                return result_path(result)
+                    .parent()
+                    .map(|x| x.to_string_lossy().into_owned())
+            }
+            Ok(stat) => stat,
+            Err(_) => return None,
+        };
+        match self {
+            GroupBy::Parent => None,
+            GroupBy::Host => remote_location(stat.remote.as_deref()?).map(|x| x.0),
+            GroupBy::Owner => {
+                let (host, path) = remote_location(stat.remote.as_deref()?)?;
+                let owner = path.split('/').next().filter(|x| !x.is_empty())?;
+                Some(format!("{}/{}", host, owner))
+            }
+            GroupBy::Label => stat.label.clone(),
+        }
+    }
+
+    /// The heading for results without a key
+    pub fn missing(self) -> &'static str {
+        match self {
+            GroupBy::Parent => "(no parent)",
+            GroupBy::Host | GroupBy::Owner => "(no remote)",
+            GroupBy::Label => "(no label)",
+        }
+    }
+}
+
+/// Split `results` into groups in order of their heading, keeping the order
+/// within each group. Results without a key come last.
+pub fn group(results: Vec<Result<BranchStat, RepoError>>, by: GroupBy) -> Vec<Group> {
+    let mut keys: Vec<Option<String>> = Vec::new();
+    let mut groups: Vec<Group> = Vec::new();
+    for result in results {
+        let key = by.key(&result);
+        match keys.iter().position(|x| *x == key) {
+            Some(i) => groups[i].1.push(result),
+            None => {
+                let heading = key.clone().unwrap_or_else(|| by.missing().to_string());
+                keys.push(key);
+                groups.push((heading, vec![result]));
+            }
+        }
+    }
+    let mut keyed: Vec<(Option<String>, Group)> = keys.into_iter().zip(groups).collect();
+    keyed.sort_by(|a, b| match (&a.0, &b.0) {
+        (Some(a), Some(b)) => a.cmp(b),
+        (a, b) => b.is_some().cmp(&a.is_some()),
+    });
+    keyed.into_iter().map(|x| x.1).collect()
+}
+
+pub fn result_path(result: &Result<BranchStat, RepoError>) -> &Path {
+    match result {
+        Ok(stat) => &stat.path,
+        Err(err) => &err.path,
+    }
+}
+
+/// Split a remote URL into its host and path, without any `.git` suffix.
+/// Local paths have no host and give `None`.
+pub fn remote_location(url: &str) -> Option<(String, String)> {
+    let (host, path) = match url.split_once("://") {
+        Some(("file", _)) => return None,
+        Some((_, rest)) => rest.split_once('/').unwrap_or((rest, "")),
+        // scp-like syntax, `user@host:path`, has no slash before the colon
+        None => match url.split_once(':') {
+            Some((host, path)) if !host.contains('/') => (host, path),
+            _ => return None,
+        },
+    };
+    let host = host.rsplit('@').next().unwrap_or(host);
+    let host = host.split(':').next().unwrap_or(host);
+    if host.is_empty() {
+        return None;
+    }
+    let path = path.trim_matches('/');
+    let path = path.strip_suffix(".git").unwrap_or(path);
+    Some((host.to_lowercase(), path.to_string()))
+}
@@ -0,0 +1,444 @@
+//! JSON rendering of `BranchStat`, for `--format json` and `--format ndjson`.
+//!
+//! Each repository becomes one object. `json` prints an array of these
+//! objects, `ndjson` prints one object per line. Schema version 3:
+//!
+//! ```text
+//! {
+//!   "schema_version": 3,
+//!   "path": "/home/me/code/project",      // absolute path of the work tree
+//!   "name": "project",                    // directory name, whatever --names says
+//!   "branch": "main",                     // null when HEAD is not on a branch
+//!   "head": {                             // what is checked out
+//!     "kind": "branch",                   // branch, detached or unborn
+//!     "name": "main",                     // branch name, null when detached
+//!     "commit": null,                     // abbreviated commit, when detached
+//!     "tag": null                         // tag at that commit, when detached
+//!   },
+//!   "tracking": [                         // every local branch
+//!     {"name": "main", "upstream": "origin/main", "ahead": 2, "behind": 0, "gone": false}
+//!   ],
+//!   "modified": 3,                        // files with unstaged changes
+//!   "unstaged_diff": {                    // size of the unstaged changes
+//!     "files": 3, "insertions": 120, "deletions": 40, "binary": 0
+//!   },
+//!   "staged": 1,                          // files with staged changes
+//!   "staged_changes": {                   // the same files by kind of change
+//!     "added": 0, "modified": 1, "deleted": 0, "renamed": 0, "copied": 0
+//!   },
+//!   "staged_diff": {"files": 1, "insertions": 2, "deletions": 0, "binary": 0},
+//!   "untracked": 4,                       // untracked, non-ignored files
+//!   "unmerged": 1,                        // paths with merge conflicts
+//!   "conflicts": {                        // the same paths by kind of conflict
+//!     "both_modified": 1, "both_added": 0, "both_deleted": 0,
+//!     "added_by_us": 0, "added_by_them": 0, "deleted_by_us": 0, "deleted_by_them": 0
+//!   },
+//!   "stashes": [                          // newest first
+//!     {"branch": "main", "time": 1700000000, "message": "WIP on main: 1a2b3c4 Fix"}
+//!   ],
+//!   "operation": {                        // null when nothing is in progress
+//!     "kind": "rebase",                   // merge, rebase, am, cherry-pick, revert, bisect
+//!     "step": 3, "total": 7               // null when unknown or not applicable
+//!   },
+//!   "last_commit": 1700000000,            // committer time of HEAD, null if unborn
+//!   "remote": "git@github.com:me/project.git", // origin URL, or null
+//!   "label": "work",                      // branchstat.label git config, else the
+//!                                         // manifest group or a [labels] match, or null
+//!   "mismatches": [                       // how it differs from its --manifest entry
+//!     {"kind": "remote", "expected": "git@github.com:team/project.git",
+//!      "actual": "git@github.com:me/project.git"},  // actual is null without origin
+//!     {"kind": "default_branch", "expected": "main", "actual": null}
+//!   ]
+//! }
+//! ```
+//!
+//! A repository that could not be inspected becomes a shorter object:
+//!
+//! ```text
+//! {
+//!   "schema_version": 3,
+//!   "path": "/home/me/code/broken",
+//!   "name": "broken",
+//!   "error": {
+//!     "kind": "not_a_repository",         // git_not_found, not_found,
+//!                                         // not_a_directory, not_a_repository,
+//!                                         // dubious_ownership, timeout, git,
+//!                                         // parse, missing or other
+//!     "message": "not a git repository"
+//!   }
+//! }
+//! ```
+//!
+//! With `--manifest`, entries missing on disk are reported the same way, as
+//! `missing`. Repositories found beneath the paths that the manifest does
+//! not list are not inspected and come last, as
+//! `{"schema_version": 3, "path": ..., "name": ..., "unlisted": true}`.
+//!
+//! `--summary-only` prints a single object of counts across every scanned
+//! repository instead:
+//!
+//! ```text
+//! {
+//!   "schema_version": 3,
+//!   "repos": 12, "clean": 7, "dirty": 3, "ahead": 2, "behind": 1,
+//!   "stashed": 1, "errors": 0,                // repositories in each state
+//!   "unpushed": 5,                            // commits, across all branches
+//!   "modified": 9, "untracked": 4,            // files
+//!   "unlisted": 0                             // not in the manifest, nor in repos
+//! }
+//! ```
+//!
+//! New fields may be added without changing `schema_version`. Removing,
+//! renaming or changing the meaning of a field bumps it.
+//!
+//! Version history:
+//! - 3: `operation` has no `interactive` field, since git no longer leaves
+//!   a way to tell plain and interactive rebases apart, and repositories
+//!   the manifest does not list are no longer errors
+//! - 2: `tracking` lists every local branch, not only diverged ones
+//! - 1: initial schema
+use crate::manifest::Mismatch;
+use crate::totals::Totals;
+use crate::{
+    BranchStat, Conflicts, DiffStat, Head, Operation, RepoError, StagedChanges, Stash, Tracking,
+};
+use std::fmt;
+use std::path::Path;
+
+pub const SCHEMA_VERSION: u64 = 3;
+
+/// A minimal JSON value, enough to serialise our own types
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(u64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(&'static str, Value)>),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Null => write!(f, "null"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Number(n) => write!(f, "{}", n),
+            Value::String(s) => write_string(f, s),
+            Value::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Value::Object(fields) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Value {
+        Value::String(s.to_string())
+    }
+}
+
+impl From<usize> for Value {
+    fn from(n: usize) -> Value {
+        Value::Number(n as u64)
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(o: Option<T>) -> Value {
+        o.map(Into::into).unwrap_or(Value::Null)
+    }
+}
+
+impl From<&Head> for Value {
+    fn from(h: &Head) -> Value {
+        let (kind, commit, tag) = match h {
+            Head::Branch(_) => ("branch", None, None),
+            Head::Detached { commit, tag } => ("detached", Some(commit.as_str()), tag.as_deref()),
+            Head::Unborn(_) => ("unborn", None, None),
+        };
+        Value::Object(vec![
+            ("kind", kind.into()),
+            ("name", h.branch().into()),
+            ("commit", commit.into()),
+            ("tag", tag.into()),
+        ])
+    }
+}
+
+impl From<&Tracking> for Value {
+    fn from(t: &Tracking) -> Value {
+        Value::Object(vec![
+            ("name", t.name.as_str().into()),
+            ("upstream", t.upstream.as_deref().into()),
+            ("ahead", t.ahead.into()),
+            ("behind", t.behind.into()),
+            ("gone", Value::Bool(t.gone)),
+        ])
+    }
+}
+
+impl From<&DiffStat> for Value {
+    fn from(d: &DiffStat) -> Value {
+        Value::Object(vec![
+            ("files", d.files.into()),
+            ("insertions", d.insertions.into()),
+            ("deletions", d.deletions.into()),
+            ("binary", d.binary.into()),
+        ])
+    }
+}
+
+impl From<&StagedChanges> for Value {
+    fn from(s: &StagedChanges) -> Value {
+        Value::Object(vec![
+            ("added", s.added.into()),
+            ("modified", s.modified.into()),
+            ("deleted", s.deleted.into()),
+            ("renamed", s.renamed.into()),
+            ("copied", s.copied.into()),
+        ])
+    }
+}
+
+impl From<&Conflicts> for Value {
+    fn from(c: &Conflicts) -> Value {
+        Value::Object(vec![
+            ("both_modified", c.both_modified.into()),
+            ("both_added", c.both_added.into()),
+            ("both_deleted", c.both_deleted.into()),
+            ("added_by_us", c.added_by_us.into()),
+            ("added_by_them", c.added_by_them.into()),
+            ("deleted_by_us", c.deleted_by_us.into()),
+            ("deleted_by_them", c.deleted_by_them.into()),
+        ])
+    }
+}
+
+impl From<&Stash> for Value {
+    fn from(s: &Stash) -> Value {
+        Value::Object(vec![
+            ("branch", s.branch.as_deref().into()),
+            ("time", Value::Number(s.time)),
+            ("message", s.message.as_str().into()),
+        ])
+    }
+}
+
+impl From<&Mismatch> for Value {
+    fn from(m: &Mismatch) -> Value {
+        let (expected, actual) = match m {
+            Mismatch::Remote { expected, actual } => (expected, actual.as_deref()),
+            Mismatch::DefaultBranch(expected) => (expected, None),
+        };
+        Value::Object(vec![
+            ("kind", m.kind().into()),
+            ("expected", expected.as_str().into()),
+            ("actual", actual.into()),
+        ])
+    }
+}
+
+impl From<&Operation> for Value {
+    fn from(op: &Operation) -> Value {
+        let (kind, step) = match op {
+            Operation::Merge => ("merge", None),
+            Operation::Rebase { step } => ("rebase", *step),
+            Operation::ApplyMailbox { step } => ("am", *step),
+            Operation::CherryPick => ("cherry-pick", None),
+            Operation::Revert => ("revert", None),
+            Operation::Bisect => ("bisect", None),
+        };
+        Value::Object(vec![
+            ("kind", kind.into()),
+            ("step", step.map(|(x, _)| x).into()),
+            ("total", step.map(|(_, x)| x).into()),
+        ])
+    }
+}
+
+impl From<&BranchStat> for Value {
+    fn from(stat: &BranchStat) -> Value {
+        Value::Object(vec![
+            ("schema_version", Value::Number(SCHEMA_VERSION)),
+            ("path", stat.path.to_string_lossy().as_ref().into()),
+            (
+                "name",
+                stat.path
+                    .file_name()
+                    .map(|x| x.to_string_lossy())
+                    .as_deref()
+                    .into(),
+            ),
+            ("branch", stat.head.branch().into()),
+            ("head", (&stat.head).into()),
+            (
+                "tracking",
+                Value::Array(stat.tracking.iter().map(Into::into).collect()),
+            ),
+            ("modified", stat.modified.into()),
+            ("unstaged_diff", (&stat.unstaged_diff).into()),
+            ("staged", stat.staged.total().into()),
+            ("staged_changes", (&stat.staged).into()),
+            ("staged_diff", (&stat.staged_diff).into()),
+            ("untracked", stat.untracked.into()),
+            ("unmerged", stat.conflicts.total().into()),
+            ("conflicts", (&stat.conflicts).into()),
+            (
+                "stashes",
+                Value::Array(stat.stashes.iter().map(Into::into).collect()),
+            ),
+            ("operation", stat.operation.as_ref().into()),
+            ("last_commit", stat.last_commit.map(Value::Number).into()),
+            ("remote", stat.remote.as_deref().into()),
+            ("label", stat.label.as_deref().into()),
+            (
+                "mismatches",
+                Value::Array(stat.mismatches.iter().map(Into::into).collect()),
+            ),
+        ])
+    }
+}
+
+impl From<&RepoError> for Value {
+    fn from(err: &RepoError) -> Value {
+        Value::Object(vec![
+            ("schema_version", Value::Number(SCHEMA_VERSION)),
+            ("path", err.path.to_string_lossy().as_ref().into()),
+            (
+                "name",
+                err.path
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .as_ref()
+                    .into(),
+            ),
+            (
+                "error",
+                Value::Object(vec![
+                    ("kind", err.kind().into()),
+                    ("message", err.to_string().as_str().into()),
+                ]),
+            ),
+        ])
+    }
+}
+
+impl From<&Result<BranchStat, RepoError>> for Value {
+    fn from(result: &Result<BranchStat, RepoError>) -> Value {
+        match result {
+            Ok(stat) => stat.into(),
+            Err(err) => err.into(),
+        }
+    }
+}
+
+impl From<&Totals> for Value {
+    fn from(t: &Totals) -> Value {
+        Value::Object(vec![
+            ("schema_version", Value::Number(SCHEMA_VERSION)),
+            ("repos", t.repos.into()),
+            ("clean", t.clean.into()),
+            ("dirty", t.dirty.into()),
+            ("ahead", t.ahead.into()),
+            ("behind", t.behind.into()),
+            ("stashed", t.stashed.into()),
+            ("errors", t.errors.into()),
+            ("unpushed", t.unpushed.into()),
+            ("modified", t.modified.into()),
+            ("untracked", t.untracked.into()),
+            ("unlisted", t.unlisted.into()),
+        ])
+    }
+}
+
+/// The entry printed for a repository by `branches`, a subset of the full
+/// object with only `path`, `name`, `branch` and `head`
+pub fn head_entry(path: &Path, head: &Head) -> Value {
+    Value::Object(vec![
+        ("schema_version", Value::Number(SCHEMA_VERSION)),
+        ("path", path.to_string_lossy().as_ref().into()),
+        (
+            "name",
+            path.file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .as_ref()
+                .into(),
+        ),
+        ("branch", head.branch().into()),
+        ("head", head.into()),
+    ])
+}
+
+/// A repository found beneath the scanned paths that the manifest does not
+/// list, which was not inspected
+pub fn unlisted_entry(path: &Path) -> Value {
+    Value::Object(vec![
+        ("schema_version", Value::Number(SCHEMA_VERSION)),
+        ("path", path.to_string_lossy().as_ref().into()),
+        (
+            "name",
+            path.file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .as_ref()
+                .into(),
+        ),
+        ("unlisted", Value::Bool(true)),
+    ])
+}
+
+/// Render objects as a single JSON array, one object per line
+pub fn render_json(objects: impl IntoIterator<Item = Value>) -> String {
+    let objects: Vec<String> = objects.into_iter().map(|x| x.to_string()).collect();
+    if objects.is_empty() {
+        "[]".to_string()
+    } else {
+        format!("[\n{}\n]", objects.join(",\n"))
+    }
+}
+
+/// Render objects as newline-delimited JSON
+pub fn render_ndjson(objects: impl IntoIterator<Item = Value>) -> String {
+    objects
+        .into_iter()
+        .map(|x| x.to_string())
+        .collect::<Vec<String>>()
+        .join("\n")
+}
@@ -0,0 +1,473 @@
+pub mod check;
+pub mod config;
+pub mod error;
+pub mod filter;
+mod glob;
+pub mod group;
+pub mod json;
+pub mod manifest;
+#[cfg(feature = "native")]
+pub mod native;
+pub mod operation;
+pub mod repo;
+pub mod style;
+pub mod subprocess;
+pub mod table;
+mod toml;
+pub mod totals;
+
+use anyhow::{anyhow, Result};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+pub use error::{Error, RepoError};
+#[cfg(feature = "native")]
+pub use native::Native;
+pub use operation::Operation;
+pub use style::{ColorChoice, Symbols, Theme};
+pub use subprocess::{Collector, Subprocess};
+
+/// The state of a single repository, as gathered by `branchstat`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BranchStat {
+    pub path: PathBuf,
+    /// What is checked out
+    pub head: Head,
+    /// Every local branch and how it compares with its upstream
+    pub tracking: Vec<Tracking>,
+    /// Files with unstaged changes
+    pub modified: usize,
+    /// Lines changed by the unstaged changes
+    pub unstaged_diff: DiffStat,
+    /// Files with staged changes, by kind of change
+    pub staged: StagedChanges,
+    /// Lines changed by the staged changes
+    pub staged_diff: DiffStat,
+    /// Untracked files, respecting the standard excludes
+    pub untracked: usize,
+    /// Paths with unresolved merge conflicts, by kind of conflict
+    pub conflicts: Conflicts,
+    /// Stash entries, newest first
+    pub stashes: Vec<Stash>,
+    /// A merge, rebase or similar that has not finished
+    pub operation: Option<Operation>,
+    /// Committer time of HEAD in seconds since the Unix epoch, if it has one
+    pub last_commit: Option<u64>,
+    /// URL of the `origin` remote
+    pub remote: Option<String>,
+    /// The `branchstat.label` git config value, for grouping. The command
+    /// line tool falls back to the manifest group or a `[labels]` match.
+    pub label: Option<String>,
+    /// How the repository differs from its `--manifest` entry
+    pub mismatches: Vec<manifest::Mismatch>,
+}
+
+/// What HEAD points at
+#[derive(Debug, Clone, PartialEq)]
+pub enum Head {
+    Branch(String),
+    /// Not on a branch, with the abbreviated commit and a tag pointing at it
+    Detached {
+        commit: String,
+        tag: Option<String>,
+    },
+    /// On a branch with no commits yet, as in a freshly created repository
+    Unborn(String),
+}
+
+impl Default for Head {
+    fn default() -> Head {
+        Head::Unborn("master".to_string())
+    }
+}
+
+impl Head {
+    /// The checked out branch, whether or not it has any commits
+    pub fn branch(&self) -> Option<&str> {
+        match self {
+            Head::Branch(name) | Head::Unborn(name) => Some(name),
+            Head::Detached { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for Head {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Head::Branch(name) => write!(f, "{}", name),
+            Head::Detached { tag: Some(tag), .. } => write!(f, "({})", tag),
+            Head::Detached { commit, .. } => write!(f, "({})", commit),
+            Head::Unborn(name) => write!(f, "{} (no commits)", name),
+        }
+    }
+}
+
+/// How far a local branch has diverged from its upstream
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Tracking {
+    pub name: String,
+    /// Short name of the upstream branch, if one is configured
+    pub upstream: Option<String>,
+    pub ahead: usize,
+    pub behind: usize,
+    /// The upstream is configured but no longer exists
+    pub gone: bool,
+}
+
+impl Tracking {
+    /// True when the branch is ahead of, behind or has lost its upstream
+    pub fn is_diverged(&self) -> bool {
+        self.ahead > 0 || self.behind > 0 || self.gone
+    }
+}
+
+/// The size of a diff, as `git diff --numstat` reports it
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DiffStat {
+    pub files: usize,
+    pub insertions: usize,
+    pub deletions: usize,
+    /// Files with no line counts because git treats them as binary
+    pub binary: usize,
+}
+
+impl fmt::Display for DiffStat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "+{}/-{}", self.insertions, self.deletions)?;
+        if self.binary > 0 {
+            write!(f, ", {} binary", self.binary)?;
+        }
+        Ok(())
+    }
+}
+
+/// Staged changes by kind, with renames detected
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct StagedChanges {
+    pub added: usize,
+    /// Content, mode and type changes
+    pub modified: usize,
+    pub deleted: usize,
+    pub renamed: usize,
+    pub copied: usize,
+}
+
+impl StagedChanges {
+    pub fn total(&self) -> usize {
+        self.added + self.modified + self.deleted + self.renamed + self.copied
+    }
+
+    /// Count a change by its `git status` letter
+    pub fn add(&mut self, status: u8) {
+        match status {
+            b'A' => self.added += 1,
+            b'D' => self.deleted += 1,
+            b'R' => self.renamed += 1,
+            b'C' => self.copied += 1,
+            _ => self.modified += 1,
+        }
+    }
+}
+
+impl fmt::Display for StagedChanges {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kinds = [
+            (self.added, 'A'),
+            (self.modified, 'M'),
+            (self.deleted, 'D'),
+            (self.renamed, 'R'),
+            (self.copied, 'C'),
+        ];
+        let parts: Vec<String> = kinds
+            .iter()
+            .filter(|(n, _)| *n > 0)
+            .map(|(n, letter)| format!("{}{}", n, letter))
+            .collect();
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+/// Unmerged paths by kind of conflict
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Conflicts {
+    pub both_modified: usize,
+    pub both_added: usize,
+    pub both_deleted: usize,
+    pub added_by_us: usize,
+    pub added_by_them: usize,
+    pub deleted_by_us: usize,
+    pub deleted_by_them: usize,
+}
+
+impl Conflicts {
+    pub fn total(&self) -> usize {
+        self.both_modified
+            + self.both_added
+            + self.both_deleted
+            + self.added_by_us
+            + self.added_by_them
+            + self.deleted_by_us
+            + self.deleted_by_them
+    }
+
+    /// Count a conflict by its two letter `git status` code, such as `UU`
+    pub fn add(&mut self, code: &str) {
+        match code {
+            "AA" => self.both_added += 1,
+            "DD" => self.both_deleted += 1,
+            "AU" => self.added_by_us += 1,
+            "UA" => self.added_by_them += 1,
+            "DU" => self.deleted_by_us += 1,
+            "UD" => self.deleted_by_them += 1,
+            _ => self.both_modified += 1,
+        }
+    }
+
+    /// Count a conflict from the index stages present for its path, as a
+    /// bitmask with bit 1 for the base, 2 for ours and 3 for theirs
+    pub fn add_stages(&mut self, stages: u8) {
+        self.add(match stages >> 1 {
+            0b001 => "DD",
+            0b010 => "AU",
+            0b100 => "UA",
+            0b011 => "UD",
+            0b101 => "DU",
+            0b110 => "AA",
+            _ => "UU",
+        });
+    }
+}
+
+impl fmt::Display for Conflicts {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kinds = [
+            (self.both_modified, "UU"),
+            (self.both_added, "AA"),
+            (self.both_deleted, "DD"),
+            (self.added_by_us, "AU"),
+            (self.added_by_them, "UA"),
+            (self.deleted_by_us, "DU"),
+            (self.deleted_by_them, "UD"),
+        ];
+        let parts: Vec<String> = kinds
+            .iter()
+            .filter(|(n, _)| *n > 0)
+            .map(|(n, code)| format!("{}{}", n, code))
+            .collect();
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+/// A single `git stash` entry
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Stash {
+    /// Branch the stash was made on, or `None` if HEAD was detached
+    pub branch: Option<String>,
+    /// Creation time in seconds since the Unix epoch
+    pub time: u64,
+    pub message: String,
+}
+
+impl Stash {
+    /// Parse the branch out of a stash message such as `WIP on main: 1a2b3c4 Fix`
+    pub fn from_message(time: u64, message: &str) -> Stash {
+        let branch = message
+            .strip_prefix("WIP on ")
+            .or_else(|| message.strip_prefix("On "))
+            .and_then(|x| x.split_once(':'))
+            .map(|(branch, _)| branch)
+            .filter(|&x| x != "(no branch)")
+            .map(String::from);
+        Stash {
+            branch,
+            time,
+            message: message.to_string(),
+        }
+    }
+}
+
+/// Format a number of seconds as a short age, like `5m` or `3d`
+pub fn format_age(secs: u64) -> String {
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m", secs / 60),
+        3600..=86399 => format!("{}h", secs / 3600),
+        86400..=1209599 => format!("{}d", secs / 86400),
+        1209600..=31535999 => format!("{}w", secs / 604800),
+        _ => format!("{}y", secs / 31536000),
+    }
+}
+
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|x| x.as_secs())
+        .unwrap_or(0)
+}
+
+/// Options for text output, as drawn by `table::Table`
+#[derive(Debug, Clone, Default)]
+pub struct RenderOptions {
+    /// Show single totals instead of breakdowns, for narrow output
+    pub compact: bool,
+    pub naming: Naming,
+    /// Colors are used only for `Always`; resolve `Auto` with
+    /// `ColorChoice::resolve` first
+    pub color: ColorChoice,
+    pub theme: Theme,
+    pub symbols: Symbols,
+}
+
+/// How each repository is labelled in text output
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Naming {
+    /// The work tree directory, e.g. `branchstat`
+    #[default]
+    Dir,
+    /// The directory and its parent, e.g. `code/branchstat`
+    Parent,
+}
+
+impl FromStr for Naming {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Naming> {
+        match s {
+            "dir" => Ok(Naming::Dir),
+            "parent" => Ok(Naming::Parent),
+            _ => Err(anyhow!("Unknown naming '{}'", s)),
+        }
+    }
+}
+
+/// Label a repository by its work tree path
+pub fn repo_name(p: &Path, naming: Naming) -> String {
+    let dirname = p.file_name().unwrap_or_default().to_string_lossy();
+    match (naming, p.parent().and_then(Path::file_name)) {
+        (Naming::Parent, Some(parent)) => format!("{}/{}", parent.to_string_lossy(), dirname),
+        _ => dirname.to_string(),
+    }
+}
+
+impl BranchStat {
+    /// True when there is nothing to commit, push or pull, and nothing
+    /// differs from the manifest
+    pub fn is_clean(&self) -> bool {
+        !self.tracking.iter().any(Tracking::is_diverged)
+            && self.modified == 0
+            && self.staged.total() == 0
+            && self.untracked == 0
+            && self.conflicts.total() == 0
+            && self.stashes.is_empty()
+            && self.operation.is_none()
+            && self.mismatches.is_empty()
+    }
+
+    /// True when there are modified, staged or conflicted files, or an
+    /// unfinished operation
+    pub fn is_dirty(&self) -> bool {
+        self.modified > 0
+            || self.staged.total() > 0
+            || self.conflicts.total() > 0
+            || self.operation.is_some()
+    }
+
+    /// How urgently the repository needs looking at, highest first. Work
+    /// that exists nowhere else weighs most: conflicts and unfinished
+    /// operations 5 each, unpushed commits and stashes 3, changed files 2,
+    /// a lost upstream 2, and untracked files and commits to pull 1.
+    pub fn attention(&self) -> usize {
+        let tracking: usize = self
+            .tracking
+            .iter()
+            .map(|x| 3 * x.ahead + x.behind + if x.gone { 2 } else { 0 })
+            .sum();
+        5 * (self.conflicts.total() + usize::from(self.operation.is_some()))
+            + 3 * self.stashes.len()
+            + 2 * (self.modified + self.staged.total())
+            + self.untracked
+            + tracking
+    }
+
+    /// Seconds since the oldest stash was made
+    pub fn oldest_stash_age(&self) -> Option<u64> {
+        self.stashes
+            .iter()
+            .map(|x| now().saturating_sub(x.time))
+            .max()
+    }
+}
+
+/// A source of repository state
+pub trait Backend: Sync {
+    /// Gather the state of the work tree at `p`
+    fn branchstat(&self, p: &Path) -> Result<BranchStat>;
+}
+
+/// The optional parts of `BranchStat` a backend gathers. Each one left out
+/// saves at least one `git` command per repository. Stashes and line counts
+/// are gathered by default, the rest only when something asks for them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Collectors {
+    /// `BranchStat::stashes`
+    pub stash: bool,
+    /// Line counts in `unstaged_diff` and `staged_diff`
+    pub diffstat: bool,
+    pub last_commit: bool,
+    pub remote: bool,
+    pub label: bool,
+}
+
+impl Default for Collectors {
+    fn default() -> Collectors {
+        Collectors {
+            stash: true,
+            diffstat: true,
+            last_commit: false,
+            remote: false,
+            label: false,
+        }
+    }
+}
+
+impl Collectors {
+    pub const NAMES: [&'static str; 5] = ["stash", "diffstat", "last_commit", "remote", "label"];
+
+    /// The toggle for the collector called `name`
+    pub fn get_mut(&mut self, name: &str) -> Result<&mut bool> {
+        match name {
+            "stash" => Ok(&mut self.stash),
+            "diffstat" => Ok(&mut self.diffstat),
+            "last_commit" => Ok(&mut self.last_commit),
+            "remote" => Ok(&mut self.remote),
+            "label" => Ok(&mut self.label),
+            _ => Err(anyhow!("Unknown collector '{}'", name)),
+        }
+    }
+}
+
+/// Gather the state of the work tree at `p` with the default backend
+pub fn branchstat(p: &Path) -> Result<BranchStat> {
+    Subprocess::default().branchstat(p)
+}
+
+/// Walk a directory tree and return every git work tree beneath it,
+/// skipping directories `exclude` matches. Descent stops at a work tree, so
+/// nested repositories are not listed.
+pub fn find_repos(dir: &Path, exclude: &dyn Fn(&Path) -> bool) -> Vec<PathBuf> {
+    if dir.join(".git").exists() {
+        return vec![dir.to_path_buf()];
+    }
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    entries
+        .filter_map(|x| x.ok())
+        .filter(|x| x.file_type().map(|t| t.is_dir()).unwrap_or(false))
+        .map(|x| x.path())
+        .filter(|x| !exclude(x))
+        .flat_map(|x| find_repos(&x, exclude))
+        .collect()
+}
@@ -1,139 +1,411 @@
-use anyhow::{anyhow, Result};
+mod cli;
+
+use anyhow::Result;
+use cli::{Command, Format, Options};
+use git_branchstat::check;
+use git_branchstat::config::Config;
+use git_branchstat::group;
+use git_branchstat::json::{self, render_json, render_ndjson};
+use git_branchstat::manifest::Manifest;
+use git_branchstat::table::{self, Cell, Table};
+use git_branchstat::totals::Totals;
+use git_branchstat::{find_repos, repo, repo_name, subprocess, BranchStat, Error, Head, RepoError};
 use rayon::prelude::*;
+use std::fmt;
+use std::io::{ErrorKind, IsTerminal, Read, Write};
 use std::path::{Path, PathBuf};
-use std::process::{Command, Stdio};
 
-const VERSION: &'static str = "0.1.0";
+// `print!` and `println!` for stdout, through `write_out`
+macro_rules! out {
+    ($($arg:tt)*) => {
+        write_out(format_args!($($arg)*))
+    };
+}
+
+macro_rules! outln {
+    () => {
+        out!("\n")
+    };
+    ($($arg:tt)*) => {
+        out!("{}\n", format_args!($($arg)*))
+    };
+}
 
 fn main() {
     let args: Vec<String> = std::env::args().skip(1).collect();
-    if args.len() > 0 && args[0] == "version" {
-        println!("git-branchstat {}", VERSION);
-        std::process::exit(0);
+    let mut opts = match cli::parse_args(&args) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("{}", e);
+            // Under `check` the usual usage error code would read as a bit
+            let checking = args.iter().any(|x| x == "check");
+            std::process::exit(if checking { check::ERROR } else { 2 });
+        }
+    };
+    match (opts.command, opts.shell) {
+        (Command::Version, _) => {
+            outln!("{} {}", cli::NAME, cli::VERSION);
+            return;
+        }
+        (Command::Help, _) => {
+            out!("{}", cli::help(opts.topic));
+            return;
+        }
+        (Command::Completions, Some(shell)) => {
+            out!("{}", cli::completions(shell));
+            return;
+        }
+        _ => {}
     }
 
-    let path = &PathBuf::from(".").canonicalize().unwrap();
-    if !is_git_repo() {
-        println!("Not a git repo.");
-        std::process::exit(1);
+    let checking = opts.command == Command::Check;
+    let mut paths: Vec<PathBuf> = opts.dirs.iter().map(PathBuf::from).collect();
+    for list in &opts.lists {
+        match read_list(list) {
+            Ok(listed) => paths.extend(listed.into_iter().map(PathBuf::from)),
+            Err(e) => eprintln!("{}: {}", list, e),
+        }
     }
-    let stat = branchstat(&path);
-    if let Ok(Some(status)) = stat {
-        println!("{}", status);
+    let roots: Vec<PathBuf> = paths.iter().filter_map(|x| x.canonicalize().ok()).collect();
+    let config = match Config::load(&roots).and_then(|config| {
+        opts.apply(&config)?;
+        Ok(config)
+    }) {
+        Ok(config) => config,
+        Err(e) => settings_error(e, checking),
+    };
+    if opts.command == Command::Config {
+        out!("{}", config.show());
+        return;
     }
-}
 
-// Run a git command and return the lines of the output
-fn command_output(dir: &Path, args: &[&str]) -> Result<Vec<String>> {
-    let out = Command::new("git")
-        .current_dir(dir.to_path_buf())
-        .args(args)
-        .output()?;
-    Ok(std::str::from_utf8(&out.stdout)?
-        .lines()
-        .map(|x| x.to_string())
-        .collect())
-}
+    opts.render.color = opts.render.color.resolve(std::io::stdout().is_terminal());
+    let manifest = match opts
+        .manifest
+        .as_deref()
+        .map(|x| Manifest::load(Path::new(x)))
+    {
+        Some(Err(e)) => settings_error(e, checking),
+        Some(Ok(manifest)) => Some(manifest),
+        None => None,
+    };
+    let mut repos: Vec<PathBuf> = Vec::new();
+    if opts.dirs.is_empty() && opts.lists.is_empty() {
+        if !config.roots.value.is_empty() {
+            paths = config.roots.value.clone();
+        } else if manifest.is_none() {
+            match repo::current_top_level() {
+                Ok(top) => repos.push(top),
+                Err(_) => {
+                    if !opts.quiet {
+                        outln!("Not a git repo.");
+                    }
+                    std::process::exit(if checking { check::ERROR } else { 1 });
+                }
+            }
+        }
+    }
+    // Paths that are not repositories are reported alongside the results
+    let mut invalid: Vec<RepoError> = Vec::new();
+    let mut found: Vec<PathBuf> = Vec::new();
+    // Repositories the manifest leaves out are listed after the results,
+    // without being inspected
+    let mut unlisted: Vec<PathBuf> = Vec::new();
+    for path in paths {
+        match find_paths(&path, &config) {
+            Ok(repos) => found.extend(repos),
+            Err(error) => invalid.push(RepoError { path, error }),
+        }
+    }
+    match &manifest {
+        // The manifest decides what is inspected, and the paths are only
+        // searched for repositories it leaves out
+        Some(manifest) => {
+            for entry in manifest.tagged(&opts.tags) {
+                if entry.path.exists() {
+                    repos.push(entry.path.clone());
+                } else {
+                    invalid.push(RepoError {
+                        path: entry.path.clone(),
+                        error: Error::Missing(entry.remote.clone()).into(),
+                    });
+                }
+            }
+            found.sort();
+            found.dedup();
+            unlisted.extend(found.into_iter().filter(|x| manifest.get(x).is_none()));
+        }
+        None => repos.extend(found),
+    }
+    repos.sort();
+    repos.dedup();
 
-pub fn branchstat(p: &Path) -> Result<Option<String>> {
-    let outputs = vec![ahead_behind(p)?, modified(p)?, status(p)?, untracked(p)?]
+    if opts.command == Command::Branches {
+        list_branches(&opts, &repos, invalid, &unlisted);
+        return;
+    }
+
+    let backend = opts.backend();
+    let mut stats: Vec<Result<BranchStat, RepoError>> = repos
         .par_iter()
-        .filter(|&x| x.is_some())
-        .map(|x| x.as_ref().unwrap().as_str())
-        .collect::<Vec<&str>>()
-        .join(", ");
+        .map(|x| {
+            backend.branchstat(x).map_err(|error| RepoError {
+                path: x.to_path_buf(),
+                error,
+            })
+        })
+        .collect();
+    stats.extend(invalid.into_iter().map(Err));
+    // A label in the repository's own git config wins, then the manifest's
+    // group
+    for stat in stats.iter_mut().flatten() {
+        let entry = manifest.as_ref().and_then(|x| x.get(&stat.path));
+        if stat.label.is_none() {
+            stat.label = entry
+                .and_then(|x| x.group.clone())
+                .or_else(|| config.label(&stat.path).map(String::from));
+        }
+        if let Some(entry) = entry {
+            stat.mismatches = entry.mismatches(stat, opts.collectors);
+        }
+    }
+    stats.sort_by(|a, b| opts.sort.compare(a, b));
+    let failed = stats.iter().any(|x| x.is_err());
+    // Totals cover every repository scanned, including those filtered out
+    let mut totals: Totals = stats.iter().collect();
+    totals.unlisted = unlisted.len();
 
-    if outputs.is_empty() {
-        Ok(None)
-    } else {
-        let out = format!(
-            "{:20} | {}",
-            p.file_name().unwrap().to_string_lossy(),
-            outputs
-        );
-        Ok(Some(out))
+    // `check` shows only the repositories that fail, and says why in its
+    // exit code
+    let mut code = 0;
+    if checking {
+        stats.retain(|x| {
+            let bits = check::exit_code(x, &opts.fail_on);
+            code |= bits;
+            bits != 0
+        });
+        if opts.quiet {
+            std::process::exit(code);
+        }
+        unlisted.clear();
+    } else if opts.format == Format::Text || !opts.filters.filters.is_empty() {
+        // Text hides clean repositories by default, JSON lists everything
+        // unless asked to filter. Errors are always shown.
+        stats.retain(|x| x.as_ref().map_or(true, |stat| opts.filters.matches(stat)));
+    }
+
+    if opts.summary_only {
+        print_summary(&opts, &totals);
+        if failed && opts.strict {
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let groups = match opts.group_by {
+        Some(by) => group::group(stats, by),
+        None => vec![(String::new(), stats)],
+    };
+    match opts.format {
+        Format::Text => {
+            let mut table = Table::default();
+            for (heading, stats) in &groups {
+                if opts.group_by.is_some() {
+                    table.push_heading(Cell::new(heading.clone(), opts.render.theme.heading));
+                }
+                for result in stats {
+                    match result {
+                        Ok(stat) => table.push(table::cells(stat, &opts.render)),
+                        Err(err) => table.push_error(err, &opts.render),
+                    }
+                }
+                if opts.group_by.is_some() {
+                    let totals: Totals = stats.iter().collect();
+                    table.push_total(Cell::new(totals.to_string(), opts.render.theme.total));
+                }
+            }
+            push_unlisted(&mut table, &unlisted, &opts);
+            out!("{}", table.render(&opts.render, width(&opts)));
+            if !checking && totals.repos > 1 {
+                if !table.is_empty() {
+                    outln!();
+                }
+                print_summary(&opts, &totals);
+            }
+        }
+        _ => {
+            let objects: Vec<json::Value> = groups
+                .iter()
+                .flat_map(|x| x.1.iter().map(json::Value::from))
+                .chain(unlisted.iter().map(|x| json::unlisted_entry(x)))
+                .collect();
+            match opts.format {
+                Format::Json => outln!("{}", render_json(objects)),
+                _ if objects.is_empty() => {}
+                _ => outln!("{}", render_ndjson(objects)),
+            }
+        }
+    }
+    if checking {
+        std::process::exit(code);
+    }
+    if failed && opts.strict {
+        std::process::exit(1);
     }
 }
 
-fn ahead_behind(p: &Path) -> Result<Option<String>> {
-    let response: String = command_output(
-        p,
-        &[
-            "for-each-ref",
-            "--format='%(refname:short) %(upstream:track)'",
-            "refs/heads",
-        ],
-    )?
-    .par_iter()
-    .map(|x| x.trim_matches('\'').trim())
-    .filter(|x| {
-        let splits: Vec<&str> = x.split(' ').collect();
-        splits.get(1).is_some()
-    })
-    .collect();
-    if !response.is_empty() {
-        Ok(Some(response))
-    } else {
-        Ok(None)
+// Write to stdout. A reader that has gone away, like `head` once it has
+// the lines it wants, is not an error: the rest of the output is dropped
+// and the run ends with its usual exit code.
+fn write_out(args: fmt::Arguments) {
+    if let Err(e) = std::io::stdout().lock().write_fmt(args) {
+        if e.kind() != ErrorKind::BrokenPipe {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
     }
 }
 
-fn modified(p: &Path) -> Result<Option<String>> {
-    let modified = command_output(p, &["diff", "--shortstat"])?.join("\n");
-    if modified.contains("changed") {
-        let num = modified.trim_start().split(' ').collect::<Vec<&str>>()[0];
-        Ok(Some(format!("{}±", num)))
-    } else {
-        Ok(None)
+// Exit for a config or manifest file that cannot be used
+fn settings_error(e: anyhow::Error, checking: bool) -> ! {
+    eprintln!("{}", e);
+    std::process::exit(if checking { check::ERROR } else { 2 });
+}
+
+fn print_summary(opts: &Options, totals: &Totals) {
+    match opts.format {
+        Format::Text => {
+            for line in totals.summary() {
+                outln!(
+                    "{}",
+                    Cell::new(line, opts.render.theme.total).paint(opts.render.color)
+                );
+            }
+        }
+        _ => outln!("{}", json::Value::from(totals)),
     }
 }
 
-fn status(p: &Path) -> Result<Option<String>> {
-    let response = command_output(p, &["diff", "--stat", "--cached"])?;
-    if !response.is_empty() {
-        Ok(Some(format!("Staged {}", response.len())))
+// List repositories the manifest leaves out under a heading of their own
+fn push_unlisted(table: &mut Table, unlisted: &[PathBuf], opts: &Options) {
+    if unlisted.is_empty() {
+        return;
+    }
+    let heading = "Not in the manifest".to_string();
+    table.push_heading(Cell::new(heading, opts.render.theme.heading));
+    for path in unlisted {
+        table.push(vec![Cell::new(repo_name(path, opts.render.naming), "")]);
+    }
+}
+
+// The `branches` command, which only needs to know what HEAD points at
+fn list_branches(opts: &Options, repos: &[PathBuf], invalid: Vec<RepoError>, unlisted: &[PathBuf]) {
+    let mut results: Vec<Result<(PathBuf, Head), RepoError>> = repos
+        .par_iter()
+        .map(|x| match subprocess::head(x) {
+            Ok(head) => Ok((x.to_path_buf(), head)),
+            Err(error) => Err(RepoError {
+                path: x.to_path_buf(),
+                error,
+            }),
+        })
+        .collect();
+    results.extend(invalid.into_iter().map(Err));
+    results.sort_by(|a, b| {
+        let path = |x: &Result<(PathBuf, Head), RepoError>| match x {
+            Ok((path, _)) => path.clone(),
+            Err(err) => err.path.clone(),
+        };
+        path(a).cmp(&path(b))
+    });
+    let failed = results.iter().any(|x| x.is_err());
+
+    if opts.format == Format::Text {
+        let mut table = Table::default();
+        for result in &results {
+            match result {
+                Ok((path, head)) => {
+                    let style = match head {
+                        Head::Detached { .. } => opts.render.theme.detached,
+                        _ => opts.render.theme.branch,
+                    };
+                    table.push(vec![
+                        Cell::new(repo_name(path, opts.render.naming), ""),
+                        Cell::new(head.to_string(), style),
+                    ]);
+                }
+                Err(err) => table.push_error(err, &opts.render),
+            }
+        }
+        push_unlisted(&mut table, unlisted, opts);
+        out!("{}", table.render(&opts.render, width(opts)));
     } else {
-        Ok(None)
+        let lines: Vec<String> = results
+            .iter()
+            .map(|x| match x {
+                Ok((path, head)) => json::head_entry(path, head).to_string(),
+                Err(err) => json::Value::from(err).to_string(),
+            })
+            .chain(unlisted.iter().map(|x| json::unlisted_entry(x).to_string()))
+            .collect();
+        match opts.format {
+            Format::Json if lines.is_empty() => outln!("[]"),
+            Format::Json => outln!("[\n{}\n]", lines.join(",\n")),
+            _ => {
+                for line in lines {
+                    outln!("{}", line);
+                }
+            }
+        }
+    }
+    if failed && opts.strict {
+        std::process::exit(1);
     }
 }
 
-fn untracked(p: &Path) -> Result<Option<String>> {
-    let untracked = command_output(p, &["ls-files", "--others", "--exclude-standard"])?;
-    if !untracked.is_empty() {
-        Ok(Some(format!("{}?", untracked.len())))
+// The width to fit text output into, if any
+fn width(opts: &Options) -> Option<usize> {
+    if opts.wide || !std::io::stdout().is_terminal() {
+        None
     } else {
-        Ok(None)
+        table::terminal_width()
     }
 }
 
-pub fn branches(p: &Path) -> Result<Option<String>> {
-    let branches: String = command_output(p, &["branch"])?
-        .par_iter()
-        .map(|x| x.trim())
-        .filter(|x| x.starts_with('*'))
-        .map(|x| &x[2..])
-        .collect();
-    let parentpath = p.parent().ok_or(anyhow!("No parent for dir"))?;
-    let parentname = parentpath
-        .file_stem()
-        .ok_or(anyhow!("No stem for parent"))?
-        .to_string_lossy();
-    let dirname = p
-        .file_stem()
-        .ok_or(anyhow!("No stem for dir"))?
-        .to_string_lossy();
-    let dirstr = format!("{}/{}", parentname, dirname);
-    Ok(Some(format!("{:40}\t{}", dirstr, branches)))
+// The repositories at or beneath `dir`, or the repository `dir` is inside
+fn find_paths(dir: &Path, config: &Config) -> Result<Vec<PathBuf>> {
+    let path = dir.canonicalize().map_err(|e| match e.kind() {
+        ErrorKind::NotFound => Error::NotFound.into(),
+        _ => anyhow::Error::from(e),
+    })?;
+    if !path.is_dir() {
+        return Err(Error::NotADirectory.into());
+    }
+    let found = find_repos(&path, &|x| config.is_excluded(x));
+    if !found.is_empty() {
+        return Ok(found);
+    }
+    Ok(vec![repo::top_level(&path)?])
 }
 
-fn is_git_repo() -> bool {
-    let status = Command::new("git")
-        .arg("branch")
-        .stdout(Stdio::null())
-        .status()
-        .expect("Failed to check if valid git repo");
-    match status.code() {
-        Some(128) => false,
-        _ => true,
+// Paths listed in a file, or on stdin for `-`
+fn read_list(list: &str) -> Result<Vec<String>> {
+    if list == "-" {
+        read_paths(std::io::stdin().lock())
+    } else {
+        read_paths(std::fs::File::open(list)?)
     }
 }
+
+// Paths one per line, or NUL-separated as from `find -print0`
+fn read_paths(mut input: impl Read) -> Result<Vec<String>> {
+    let mut text = String::new();
+    input.read_to_string(&mut text)?;
+    let separator = if text.contains('\0') { '\0' } else { '\n' };
+    Ok(text
+        .split(separator)
+        .map(|x| x.trim_end_matches('\r'))
+        .filter(|x| !x.is_empty())
+        .map(String::from)
+        .collect())
+}
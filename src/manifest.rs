@@ -0,0 +1,193 @@
+//! A hand-maintained list of the repositories a team works on.
+//!
+//! ```toml
+//! [[repo]]
+//! path = "~/code/api"                       # relative to the manifest
+//! remote = "git@github.com:team/api.git"    # expected origin URL
+//! default_branch = "main"
+//! tags = ["backend", "go"]                  # for --tag
+//! group = "payments"                        # for --group-by label
+//! ```
+//!
+//! Only `path` is required. A `group` is used as the repository's label
+//! unless its git config sets `branchstat.label`. A repository whose
+//! `origin` is not the listed remote, or which has no local branch named
+//! `default_branch`, is reported as not matching its entry.
+use crate::config::expand_home;
+use crate::group::remote_location;
+use crate::toml::{self, Table, Value};
+use crate::{BranchStat, Collectors};
+use anyhow::{anyhow, Result};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// A repository listed in a manifest
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Entry {
+    /// The work tree, canonical if it exists
+    pub path: PathBuf,
+    pub remote: Option<String>,
+    pub default_branch: Option<String>,
+    pub tags: Vec<String>,
+    pub group: Option<String>,
+}
+
+impl Entry {
+    /// How `stat` differs from this entry. The remote is only compared when
+    /// it was collected.
+    pub fn mismatches(&self, stat: &BranchStat, collectors: Collectors) -> Vec<Mismatch> {
+        let mut mismatches = Vec::new();
+        if let (Some(expected), true) = (&self.remote, collectors.remote) {
+            if !stat
+                .remote
+                .as_deref()
+                .is_some_and(|x| same_remote(x, expected))
+            {
+                mismatches.push(Mismatch::Remote {
+                    expected: expected.clone(),
+                    actual: stat.remote.clone(),
+                });
+            }
+        }
+        if let Some(branch) = &self.default_branch {
+            let exists = stat.head.branch() == Some(branch)
+                || stat.tracking.iter().any(|x| &x.name == branch);
+            if !exists {
+                mismatches.push(Mismatch::DefaultBranch(branch.clone()));
+            }
+        }
+        mismatches
+    }
+}
+
+// The same repository, even when one URL is `https://` and the other `git@`
+fn same_remote(a: &str, b: &str) -> bool {
+    a == b || remote_location(a).is_some_and(|x| Some(x) == remote_location(b))
+}
+
+/// A way a repository differs from its manifest entry
+#[derive(Debug, Clone, PartialEq)]
+pub enum Mismatch {
+    /// `origin` is another repository, or is not set
+    Remote {
+        expected: String,
+        actual: Option<String>,
+    },
+    /// No local branch has the listed default branch's name
+    DefaultBranch(String),
+}
+
+impl Mismatch {
+    /// A stable identifier for machine-readable output
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Mismatch::Remote { .. } => "remote",
+            Mismatch::DefaultBranch(_) => "default_branch",
+        }
+    }
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Mismatch::Remote {
+                expected,
+                actual: Some(actual),
+            } => write!(f, "origin is {}, not {}", actual, expected),
+            Mismatch::Remote {
+                expected,
+                actual: None,
+            } => write!(f, "no origin, expected {}", expected),
+            Mismatch::DefaultBranch(branch) => write!(f, "no branch {}", branch),
+        }
+    }
+}
+
+/// The repositories listed in a manifest file
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Manifest {
+    pub entries: Vec<Entry>,
+}
+
+impl Manifest {
+    pub fn load(file: &Path) -> Result<Manifest> {
+        let error = |e: anyhow::Error| anyhow!("{}: {}", file.display(), e);
+        let text = std::fs::read_to_string(file).map_err(|e| error(e.into()))?;
+        let file = file.canonicalize().map_err(|e| error(e.into()))?;
+        let dir = file.parent().unwrap_or(Path::new("/"));
+        let mut manifest = Manifest::default();
+        for (key, value) in toml::parse(&text).map_err(error)? {
+            let repos = match (key.as_str(), value) {
+                ("repo", Value::Array(repos)) => repos,
+                ("repo", other) => {
+                    return Err(error(anyhow!(
+                        "repo should be [[repo]], not {}",
+                        other.type_name()
+                    )))
+                }
+                _ => return Err(error(anyhow!("unknown setting '{}'", key))),
+            };
+            for (i, repo) in repos.into_iter().enumerate() {
+                let entry = match repo {
+                    Value::Table(table) => entry(table, dir),
+                    other => Err(anyhow!("should be a table, not {}", other.type_name())),
+                };
+                manifest
+                    .entries
+                    .push(entry.map_err(|e| error(anyhow!("repo {}: {}", i + 1, e)))?);
+            }
+        }
+        Ok(manifest)
+    }
+
+    /// Entries with any of `tags`, or every entry if there are none
+    pub fn tagged<'a>(&'a self, tags: &'a [String]) -> impl Iterator<Item = &'a Entry> + 'a {
+        self.entries
+            .iter()
+            .filter(move |x| tags.is_empty() || x.tags.iter().any(|tag| tags.contains(tag)))
+    }
+
+    /// The entry for the work tree at `path`
+    pub fn get(&self, path: &Path) -> Option<&Entry> {
+        self.entries.iter().find(|x| x.path == path)
+    }
+}
+
+fn entry(table: Table, dir: &Path) -> Result<Entry> {
+    let mut entry = Entry::default();
+    for (key, value) in table {
+        match (key.as_str(), value) {
+            ("path", Value::String(path)) => {
+                let path = match expand_home(&path) {
+                    Some(path) if path.is_absolute() => path,
+                    _ => dir.join(path),
+                };
+                entry.path = path.canonicalize().unwrap_or(path);
+            }
+            ("remote", Value::String(remote)) => entry.remote = Some(remote),
+            ("default_branch", Value::String(branch)) => entry.default_branch = Some(branch),
+            ("group", Value::String(group)) => entry.group = Some(group),
+            ("tags", Value::Array(tags)) => {
+                for tag in tags {
+                    match tag {
+                        Value::String(tag) => entry.tags.push(tag),
+                        other => {
+                            return Err(anyhow!(
+                                "tags should hold strings, not {}",
+                                other.type_name()
+                            ))
+                        }
+                    }
+                }
+            }
+            ("path" | "remote" | "default_branch" | "group" | "tags", other) => {
+                return Err(anyhow!("{} should not be {}", key, other.type_name()))
+            }
+            _ => return Err(anyhow!("unknown setting '{}'", key)),
+        }
+    }
+    if entry.path.as_os_str().is_empty() {
+        return Err(anyhow!("path is required"));
+    }
+    Ok(entry)
+}
@@ -0,0 +1,232 @@
+//! An in-process backend built on libgit2, enabled with the `native` feature.
+//!
+//! No `git` process is started, so scanning many small repositories is
+//! faster. The results should match `Subprocess` for the same repository,
+//! except that libgit2 does not let a `!pattern` in a nested `.gitignore`
+//! re-include a file ignored by a parent directory.
+use crate::{repo, Backend, BranchStat, Collectors, DiffStat, Head, Operation, Stash, Tracking};
+use anyhow::Result;
+use git2::{
+    BranchType, Delta, DescribeFormatOptions, DescribeOptions, Diff, DiffFindOptions, ErrorCode,
+    IndexEntryExtendedFlag, Patch, Repository, Status, StatusOptions,
+};
+use std::collections::HashSet;
+use std::path::Path;
+
+/// Reads repository state in-process
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Native {
+    pub collectors: Collectors,
+}
+
+impl Backend for Native {
+    fn branchstat(&self, p: &Path) -> Result<BranchStat> {
+        let git_dir = repo::git_dir(p)?;
+        let repo = Repository::open(&git_dir)?;
+        repo.set_workdir(p, false)?;
+        let collect = self.collectors;
+        let mut stat = BranchStat {
+            path: p.to_path_buf(),
+            head: head(&repo)?,
+            tracking: tracking(&repo)?,
+            operation: Operation::detect(&git_dir),
+            ..Default::default()
+        };
+        if collect.stash {
+            stat.stashes = stashes(&repo)?;
+        }
+
+        // Intent-to-add entries are not staged yet, and git ignores the work
+        // tree of entries outside a sparse checkout
+        let index = repo.index()?;
+        let flagged = |flag: IndexEntryExtendedFlag| -> HashSet<Vec<u8>> {
+            index
+                .iter()
+                .filter(|x| x.flags_extended & flag.bits() != 0)
+                .map(|x| x.path)
+                .collect()
+        };
+        let intents = flagged(IndexEntryExtendedFlag::INTENT_TO_ADD);
+        let sparse = flagged(IndexEntryExtendedFlag::SKIP_WORKTREE);
+
+        let mut options = StatusOptions::new();
+        options
+            .include_untracked(true)
+            .recurse_untracked_dirs(true)
+            .renames_head_to_index(true);
+        for entry in repo.statuses(Some(&mut options))?.iter() {
+            let status = entry.status();
+            if status.is_conflicted() {
+                continue;
+            }
+            if status.is_wt_new() {
+                stat.untracked += 1;
+                continue;
+            }
+            // A rename with changes is also flagged as modified
+            if status.is_index_renamed() {
+                stat.staged.renamed += 1;
+            } else if status.is_index_new() && !intents.contains(entry.path_bytes()) {
+                stat.staged.added += 1;
+            } else if status.is_index_modified() || status.is_index_typechange() {
+                stat.staged.modified += 1;
+            } else if status.is_index_deleted() {
+                stat.staged.deleted += 1;
+            }
+            let changed = Status::WT_MODIFIED
+                | Status::WT_DELETED
+                | Status::WT_TYPECHANGE
+                | Status::WT_RENAMED;
+            if status.intersects(changed) && !sparse.contains(entry.path_bytes()) {
+                stat.modified += 1;
+            }
+        }
+        for conflict in index.conflicts()? {
+            let conflict = conflict?;
+            let stages = [&conflict.ancestor, &conflict.our, &conflict.their];
+            let mask = (0..3)
+                .filter(|&i| stages[i].is_some())
+                .fold(0, |mask, i| mask | 2 << i);
+            stat.conflicts.add_stages(mask);
+        }
+
+        // Line counts need a diff, so only ask for one when something changed
+        if collect.diffstat && stat.modified > 0 {
+            let diff = repo.diff_index_to_workdir(Some(&index), None)?;
+            stat.unstaged_diff = diffstat(&diff, &sparse)?;
+        }
+        if collect.diffstat && stat.staged.total() > 0 {
+            let tree = match repo.head() {
+                Ok(head) => Some(head.peel_to_tree()?),
+                Err(_) => None,
+            };
+            let mut diff = repo.diff_tree_to_index(tree.as_ref(), Some(&index), None)?;
+            diff.find_similar(Some(DiffFindOptions::new().renames(true)))?;
+            stat.staged_diff = diffstat(&diff, &intents)?;
+        }
+
+        if collect.last_commit {
+            if let Ok(commit) = repo.head().and_then(|x| x.peel_to_commit()) {
+                stat.last_commit = Some(commit.time().seconds().max(0) as u64);
+            }
+        }
+        let config = repo.config()?.snapshot()?;
+        if collect.remote {
+            stat.remote = config.get_string("remote.origin.url").ok();
+        }
+        if collect.label {
+            stat.label = config.get_string("branchstat.label").ok();
+        }
+        Ok(stat)
+    }
+}
+
+fn head(repo: &Repository) -> Result<Head> {
+    let head = match repo.head() {
+        Ok(head) => head,
+        Err(e) if e.code() == ErrorCode::UnbornBranch => {
+            let head = repo.find_reference("HEAD")?;
+            let target = head.symbolic_target().unwrap_or_default();
+            let name = target.strip_prefix("refs/heads/").unwrap_or(target);
+            return Ok(Head::Unborn(name.to_string()));
+        }
+        Err(e) => return Err(e.into()),
+    };
+    if head.is_branch() {
+        return Ok(Head::Branch(
+            head.shorthand().unwrap_or_default().to_string(),
+        ));
+    }
+    let commit = head.peel_to_commit()?;
+    // Like `git describe --tags --exact-match`
+    let tag = repo
+        .describe(
+            DescribeOptions::new()
+                .describe_tags()
+                .max_candidates_tags(0),
+        )
+        .and_then(|x| x.format(Some(&DescribeFormatOptions::new())))
+        .ok();
+    Ok(Head::Detached {
+        commit: commit
+            .as_object()
+            .short_id()?
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+        tag,
+    })
+}
+
+fn tracking(repo: &Repository) -> Result<Vec<Tracking>> {
+    let mut tracking = Vec::new();
+    for branch in repo.branches(Some(BranchType::Local))? {
+        let reference = branch?.0.into_reference();
+        let (name, oid) = match (reference.shorthand(), reference.target()) {
+            (Some(name), Some(oid)) => (name, oid),
+            _ => continue,
+        };
+        let mut branch = Tracking {
+            name: name.to_string(),
+            ..Default::default()
+        };
+        // The configured upstream, whether or not it still exists
+        let upstream = match repo.branch_upstream_name(reference.name().unwrap_or_default()) {
+            Ok(upstream) => upstream.as_str().unwrap_or_default().to_string(),
+            Err(_) => {
+                tracking.push(branch);
+                continue;
+            }
+        };
+        match repo.refname_to_id(&upstream) {
+            Ok(upstream) => {
+                let (ahead, behind) = repo.graph_ahead_behind(oid, upstream)?;
+                branch.ahead = ahead;
+                branch.behind = behind;
+            }
+            Err(_) => branch.gone = true,
+        }
+        let short = ["refs/heads/", "refs/remotes/"]
+            .iter()
+            .find_map(|x| upstream.strip_prefix(x))
+            .unwrap_or(&upstream);
+        branch.upstream = Some(short.to_string());
+        tracking.push(branch);
+    }
+    tracking.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(tracking)
+}
+
+/// Stash entries from the `refs/stash` reflog, newest first
+fn stashes(repo: &Repository) -> Result<Vec<Stash>> {
+    let mut stashes = Vec::new();
+    for entry in repo.reflog("refs/stash")?.iter() {
+        let time = repo.find_commit(entry.id_new())?.time().seconds();
+        let message = entry.message().unwrap_or_default();
+        stashes.push(Stash::from_message(time.max(0) as u64, message));
+    }
+    Ok(stashes)
+}
+
+// Sum the line counts of every file in a diff, as `git diff --numstat` does,
+// leaving out `skip`. Unmerged paths are left to the conflict counts.
+fn diffstat(diff: &Diff, skip: &HashSet<Vec<u8>>) -> Result<DiffStat> {
+    let mut stat = DiffStat::default();
+    for (i, delta) in diff.deltas().enumerate() {
+        let path = delta.new_file().path_bytes().unwrap_or_default();
+        if delta.status() == Delta::Conflicted || skip.contains(path) {
+            continue;
+        }
+        stat.files += 1;
+        // No patch is made for a binary file
+        match Patch::from_diff(diff, i)? {
+            Some(patch) if !patch.delta().flags().is_binary() => {
+                let (_, insertions, deletions) = patch.line_stats()?;
+                stat.insertions += insertions;
+                stat.deletions += deletions;
+            }
+            _ => stat.binary += 1,
+        }
+    }
+    Ok(stat)
+}
@@ -0,0 +1,81 @@
+//! Detection of operations left in progress in a repository, such as a
+//! conflicted merge or a rebase stopped part way through.
+use std::fmt;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operation {
+    Merge,
+    /// `step` is the current and total number of commits, when known. Plain
+    /// and interactive rebases are not told apart, since git 2.26 and later
+    /// leave the same state behind for both.
+    Rebase {
+        step: Option<(usize, usize)>,
+    },
+    /// `git am`, which shares its state directory with `git rebase --apply`
+    ApplyMailbox {
+        step: Option<(usize, usize)>,
+    },
+    CherryPick,
+    Revert,
+    Bisect,
+}
+
+impl Operation {
+    /// Look for the state files git leaves in `git_dir` while an operation runs
+    pub fn detect(git_dir: &Path) -> Option<Operation> {
+        let merge = git_dir.join("rebase-merge");
+        if merge.is_dir() {
+            return Some(Operation::Rebase {
+                step: step(&merge, "msgnum", "end"),
+            });
+        }
+        let apply = git_dir.join("rebase-apply");
+        if apply.is_dir() {
+            let step = step(&apply, "next", "last");
+            if apply.join("applying").exists() {
+                return Some(Operation::ApplyMailbox { step });
+            }
+            return Some(Operation::Rebase { step });
+        }
+        let files = [
+            ("MERGE_HEAD", Operation::Merge),
+            ("CHERRY_PICK_HEAD", Operation::CherryPick),
+            ("REVERT_HEAD", Operation::Revert),
+            ("BISECT_LOG", Operation::Bisect),
+        ];
+        files
+            .iter()
+            .find(|(file, _)| git_dir.join(file).exists())
+            .map(|&(_, op)| op)
+    }
+}
+
+fn step(dir: &Path, current: &str, total: &str) -> Option<(usize, usize)> {
+    let read = |name| {
+        std::fs::read_to_string(dir.join(name))
+            .ok()?
+            .trim()
+            .parse()
+            .ok()
+    };
+    Some((read(current)?, read(total)?))
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (name, step) = match self {
+            Operation::Merge => ("MERGE", None),
+            Operation::Rebase { step } => ("REBASE", *step),
+            Operation::ApplyMailbox { step } => ("AM", *step),
+            Operation::CherryPick => ("CHERRY-PICK", None),
+            Operation::Revert => ("REVERT", None),
+            Operation::Bisect => ("BISECT", None),
+        };
+        write!(f, "{}", name)?;
+        if let Some((current, total)) = step {
+            write!(f, " {}/{}", current, total)?;
+        }
+        Ok(())
+    }
+}
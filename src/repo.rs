@@ -0,0 +1,88 @@
+//! Locating the git directory that belongs to a work tree.
+use crate::Error;
+use anyhow::{anyhow, Result};
+use std::path::{Path, PathBuf};
+
+/// The git directory of the work tree at `work_tree`. This is usually
+/// `.git`, but worktrees and submodules use a `.git` file pointing elsewhere,
+/// and `GIT_DIR` overrides both for the work tree it applies to.
+pub fn git_dir(work_tree: &Path) -> Result<PathBuf> {
+    if let Some(dir) = overridden_git_dir(work_tree) {
+        return Ok(dir);
+    }
+    let dotgit = work_tree.join(".git");
+    if dotgit.is_dir() {
+        return Ok(dotgit);
+    }
+    if dotgit.is_file() {
+        let text = std::fs::read_to_string(&dotgit)?;
+        let target = text
+            .trim()
+            .strip_prefix("gitdir:")
+            .ok_or(anyhow!("Invalid .git file in {}", work_tree.display()))?;
+        return Ok(work_tree.join(target.trim()));
+    }
+    Err(Error::NotARepository.into())
+}
+
+/// The root of the work tree git would use from the current directory
+pub fn current_top_level() -> Result<PathBuf> {
+    match env_override() {
+        Some((_, tree)) => Ok(tree),
+        None => top_level(Path::new(".")),
+    }
+}
+
+/// The root of the work tree containing `start`, which may be a
+/// subdirectory of it or a directory inside its git dir.
+pub fn top_level(start: &Path) -> Result<PathBuf> {
+    let start = start.canonicalize()?;
+    if let Some((_, tree)) = env_override() {
+        if start.starts_with(&tree) {
+            return Ok(tree);
+        }
+    }
+    for dir in start.ancestors() {
+        if dir.join(".git").exists() {
+            return Ok(dir.to_path_buf());
+        }
+        // A linked worktree's git dir records where its `.git` file lives
+        if let Ok(text) = std::fs::read_to_string(dir.join("gitdir")) {
+            if let Some(tree) = Path::new(text.trim()).parent() {
+                return Ok(tree.to_path_buf());
+            }
+        }
+        if dir.file_name().is_some_and(|x| x == ".git") {
+            if let Some(tree) = dir.parent() {
+                return Ok(tree.to_path_buf());
+            }
+        }
+    }
+    Err(Error::NotARepository.into())
+}
+
+/// `GIT_DIR`, if it applies to the work tree at `work_tree`
+pub(crate) fn overridden_git_dir(work_tree: &Path) -> Option<PathBuf> {
+    let (dir, tree) = env_override()?;
+    Some(dir).filter(|_| same_dir(&tree, work_tree))
+}
+
+// `GIT_DIR` and the work tree it belongs to, which is `GIT_WORK_TREE` or
+// else the current directory, as git itself treats them
+fn env_override() -> Option<(PathBuf, PathBuf)> {
+    let dir = PathBuf::from(std::env::var_os("GIT_DIR")?)
+        .canonicalize()
+        .ok()?;
+    let tree = match std::env::var_os("GIT_WORK_TREE") {
+        Some(tree) => PathBuf::from(tree),
+        None => std::env::current_dir().ok()?,
+    };
+    Some((dir, tree.canonicalize().ok()?))
+}
+
+fn same_dir(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
@@ -0,0 +1,228 @@
+//! Colors and symbols for text output.
+use anyhow::{anyhow, Result};
+use std::str::FromStr;
+
+/// Whether text output is colored
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ColorChoice {
+    /// Color when writing to a terminal, unless `NO_COLOR` is set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for ColorChoice {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<ColorChoice> {
+        match s {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(anyhow!("Unknown color choice '{}'", s)),
+        }
+    }
+}
+
+impl ColorChoice {
+    /// Settle `Auto` for output going to a terminal or not. An explicit
+    /// choice wins over `NO_COLOR`, following https://no-color.org.
+    pub fn resolve(self, is_terminal: bool) -> ColorChoice {
+        let no_color = std::env::var_os("NO_COLOR").is_some_and(|x| !x.is_empty());
+        let dumb = std::env::var_os("TERM").is_some_and(|x| x == "dumb");
+        match self {
+            ColorChoice::Auto if is_terminal && !no_color && !dumb => ColorChoice::Always,
+            ColorChoice::Auto => ColorChoice::Never,
+            choice => choice,
+        }
+    }
+}
+
+/// ANSI SGR parameters for each part of a line, such as `"1;31"` for bold
+/// red. Empty parameters leave the part unstyled.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Theme {
+    pub branch: &'static str,
+    pub detached: &'static str,
+    pub operation: &'static str,
+    pub ahead: &'static str,
+    pub behind: &'static str,
+    pub gone: &'static str,
+    pub conflicts: &'static str,
+    pub dirty: &'static str,
+    pub staged: &'static str,
+    pub untracked: &'static str,
+    pub stash: &'static str,
+    pub clean: &'static str,
+    pub error: &'static str,
+    /// Group headings
+    pub heading: &'static str,
+    /// Subtotal lines
+    pub total: &'static str,
+}
+
+impl Theme {
+    pub const DEFAULT: Theme = Theme {
+        branch: "1",
+        detached: "1;33",
+        operation: "1;35",
+        ahead: "32",
+        behind: "31",
+        gone: "2;31",
+        conflicts: "1;31",
+        dirty: "33",
+        staged: "36",
+        untracked: "34",
+        stash: "35",
+        clean: "32",
+        error: "31",
+        heading: "1;4",
+        total: "2",
+    };
+
+    /// The default colors in their bright variants, for dark terminals
+    pub const BRIGHT: Theme = Theme {
+        branch: "1;97",
+        detached: "1;93",
+        operation: "1;95",
+        ahead: "92",
+        behind: "91",
+        gone: "2;91",
+        conflicts: "1;91",
+        dirty: "93",
+        staged: "96",
+        untracked: "94",
+        stash: "95",
+        clean: "92",
+        error: "91",
+        heading: "1;4;97",
+        total: "2",
+    };
+
+    /// No colors, only weight and underlines
+    pub const MONO: Theme = Theme {
+        branch: "1",
+        detached: "4",
+        operation: "1",
+        ahead: "",
+        behind: "4",
+        gone: "2",
+        conflicts: "1;4",
+        dirty: "1",
+        staged: "",
+        untracked: "2",
+        stash: "2",
+        clean: "2",
+        error: "1",
+        heading: "1;4",
+        total: "2",
+    };
+
+    /// Wrap `text` in the escape codes for `style`
+    pub fn paint(style: &str, text: &str) -> String {
+        if style.is_empty() {
+            text.to_string()
+        } else {
+            format!("\x1b[{}m{}\x1b[0m", style, text)
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme::DEFAULT
+    }
+}
+
+impl FromStr for Theme {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Theme> {
+        match s {
+            "default" => Ok(Theme::DEFAULT),
+            "bright" => Ok(Theme::BRIGHT),
+            "mono" => Ok(Theme::MONO),
+            _ => Err(anyhow!("Unknown theme '{}'", s)),
+        }
+    }
+}
+
+/// The markers used for each count
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Symbols {
+    pub ahead: &'static str,
+    pub behind: &'static str,
+    pub gone: &'static str,
+    pub conflicts: &'static str,
+    pub dirty: &'static str,
+    pub staged: &'static str,
+    pub untracked: &'static str,
+    pub stash: &'static str,
+    /// Shown for a repository with nothing to report
+    pub clean: &'static str,
+    /// Replaces text cut out to fit the terminal
+    pub ellipsis: &'static str,
+}
+
+impl Symbols {
+    pub const UNICODE: Symbols = Symbols {
+        ahead: "↑",
+        behind: "↓",
+        gone: "✗",
+        conflicts: "!",
+        dirty: "±",
+        staged: "Staged ",
+        untracked: "?",
+        stash: "$",
+        clean: "✓",
+        ellipsis: "…",
+    };
+
+    /// For terminals and fonts without arrows, in the style of git's prompt
+    pub const ASCII: Symbols = Symbols {
+        ahead: ">",
+        behind: "<",
+        gone: "x",
+        conflicts: "!",
+        dirty: "*",
+        staged: "Staged ",
+        untracked: "?",
+        stash: "$",
+        clean: "ok",
+        ellipsis: "...",
+    };
+
+    /// Icons from a patched Nerd Font
+    pub const NERD: Symbols = Symbols {
+        ahead: "\u{f062}",
+        behind: "\u{f063}",
+        gone: "\u{f00d}",
+        conflicts: "\u{f071}",
+        dirty: "\u{f040}",
+        staged: "\u{f00c} ",
+        untracked: "\u{f128}",
+        stash: "\u{f01c}",
+        clean: "\u{f05d}",
+        ellipsis: "…",
+    };
+}
+
+impl Default for Symbols {
+    fn default() -> Symbols {
+        Symbols::UNICODE
+    }
+}
+
+impl FromStr for Symbols {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Symbols> {
+        match s {
+            "unicode" => Ok(Symbols::UNICODE),
+            "ascii" => Ok(Symbols::ASCII),
+            "nerd" => Ok(Symbols::NERD),
+            _ => Err(anyhow!("Unknown symbol set '{}'", s)),
+        }
+    }
+}
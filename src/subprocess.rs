@@ -0,0 +1,482 @@
+//! The default backend, which runs the `git` binary for every query.
+use crate::{
+    repo, Backend, BranchStat, Collectors, Conflicts, DiffStat, Error, Head, Operation,
+    StagedChanges, Stash, Tracking,
+};
+use anyhow::{anyhow, Result};
+use rayon::prelude::*;
+use std::io::Read;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+/// How the work tree state of a repository is gathered
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Collector {
+    /// A single `git status --porcelain=v2` call
+    #[default]
+    Porcelain,
+    /// Separate `diff`, `ls-files` and `for-each-ref` calls
+    Legacy,
+}
+
+impl FromStr for Collector {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Collector> {
+        match s {
+            "porcelain" => Ok(Collector::Porcelain),
+            "legacy" => Ok(Collector::Legacy),
+            _ => Err(anyhow!("Unknown collector '{}'", s)),
+        }
+    }
+}
+
+/// Reads repository state by running `git` subprocesses
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Subprocess {
+    pub collector: Collector,
+    pub collectors: Collectors,
+}
+
+/// How long a single git command may run before it is killed
+pub const TIMEOUT: Duration = Duration::from_secs(60);
+
+struct Output {
+    code: Option<i32>,
+    stdout: Vec<u8>,
+    stderr: String,
+}
+
+// Run a git command to completion, or until it exceeds `TIMEOUT`. The
+// variables that point git at a repository are only passed on for the work
+// tree they belong to, so other repositories are not read through them.
+// Messages are kept untranslated so errors can be told apart by their text.
+fn run(dir: &Path, args: &[&str]) -> Result<Output> {
+    let mut command = Command::new("git");
+    command
+        .current_dir(dir)
+        .env("LC_ALL", "C")
+        .env_remove("GIT_DIR")
+        .env_remove("GIT_WORK_TREE")
+        .env_remove("GIT_INDEX_FILE");
+    if let Some(git_dir) = repo::overridden_git_dir(dir) {
+        command
+            .arg("--git-dir")
+            .arg(git_dir)
+            .arg("--work-tree")
+            .arg(dir);
+        if let Some(index) = std::env::var_os("GIT_INDEX_FILE") {
+            command.env("GIT_INDEX_FILE", index);
+        }
+    }
+    let mut child = command
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => Error::GitNotFound.into(),
+            _ => anyhow::Error::from(e),
+        })?;
+    // Both pipes are drained on their own threads so neither can fill up and
+    // block git; stdout closing means git has exited
+    let (tx, rx) = mpsc::channel();
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        let _ = tx.send(stdout.read_to_end(&mut buf).map(|_| buf));
+    });
+    let mut stderr = child.stderr.take().expect("stderr is piped");
+    let stderr = thread::spawn(move || {
+        let mut buf = Vec::new();
+        let _ = stderr.read_to_end(&mut buf);
+        String::from_utf8_lossy(&buf).into_owned()
+    });
+    let stdout = match rx.recv_timeout(TIMEOUT) {
+        Ok(stdout) => stdout?,
+        Err(_) => {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(Error::Timeout(TIMEOUT).into());
+        }
+    };
+    let status = child.wait()?;
+    Ok(Output {
+        code: status.code(),
+        stdout,
+        stderr: stderr.join().unwrap_or_default(),
+    })
+}
+
+// Run a git command and return its output, failing if git does
+pub(crate) fn command_stdout(dir: &Path, args: &[&str]) -> Result<String> {
+    let out = run(dir, args)?;
+    if out.code != Some(0) {
+        return Err(Error::from_git(&args.join(" "), out.code, &out.stderr).into());
+    }
+    String::from_utf8(out.stdout)
+        .map_err(|_| Error::Parse(format!("output of `git {}`", args.join(" "))).into())
+}
+
+// Run a git command and return the lines of the output
+pub(crate) fn command_output(dir: &Path, args: &[&str]) -> Result<Vec<String>> {
+    Ok(command_stdout(dir, args)?
+        .lines()
+        .map(|x| x.to_string())
+        .collect())
+}
+
+// The first line of a query that fails when there is no answer, like
+// `symbolic-ref` on a detached HEAD. Problems with the repository itself are
+// still errors.
+fn command_optional(dir: &Path, args: &[&str]) -> Result<Option<String>> {
+    let out = run(dir, args)?;
+    if out.code != Some(0) {
+        return match Error::from_git(&args.join(" "), out.code, &out.stderr) {
+            Error::Git { .. } => Ok(None),
+            e => Err(e.into()),
+        };
+    }
+    Ok(String::from_utf8_lossy(&out.stdout)
+        .lines()
+        .next()
+        .map(String::from))
+}
+
+// The `remote.origin.url` and `branchstat.label` settings, from one `git
+// config` call. A key set more than once appears more than once, last
+// value last.
+fn config(p: &Path) -> Result<Vec<(String, String)>> {
+    let args = [
+        "config",
+        "-z",
+        "--get-regexp",
+        r"^(remote\.origin\.url|branchstat\.label)$",
+    ];
+    let out = run(p, &args)?;
+    // Exit code 1 means none of the keys is set
+    match out.code {
+        Some(0) => {}
+        Some(1) => return Ok(Vec::new()),
+        code => return Err(Error::from_git(&args.join(" "), code, &out.stderr).into()),
+    }
+    Ok(String::from_utf8_lossy(&out.stdout)
+        .split('\0')
+        .filter_map(|x| x.split_once('\n'))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect())
+}
+
+// Parse a number in git's output
+fn number<T: FromStr>(s: &str) -> Result<T> {
+    s.parse()
+        .map_err(|_| Error::Parse(format!("'{}' as a number", s)).into())
+}
+
+impl Backend for Subprocess {
+    fn branchstat(&self, p: &Path) -> Result<BranchStat> {
+        let collect = self.collectors;
+        let mut stat = match self.collector {
+            Collector::Porcelain => porcelain(p, collect)?,
+            Collector::Legacy => {
+                let unmerged = unmerged(p)?;
+                let paths: Vec<&str> = unmerged.iter().map(|x| x.0.as_str()).collect();
+                // Modified files are counted from the diff, so it always runs
+                let mut unstaged_diff = diffstat(p, false, &paths)?;
+                let modified = unstaged_diff.files;
+                let mut staged_diff = DiffStat::default();
+                if collect.diffstat {
+                    staged_diff = diffstat(p, true, &paths)?;
+                } else {
+                    unstaged_diff = DiffStat::default();
+                }
+                let mut conflicts = Conflicts::default();
+                for (_, mask) in &unmerged {
+                    conflicts.add_stages(*mask);
+                }
+                BranchStat {
+                    path: p.to_path_buf(),
+                    head: head(p)?,
+                    tracking: ahead_behind(p)?,
+                    modified,
+                    unstaged_diff,
+                    staged: status(p)?,
+                    staged_diff,
+                    untracked: untracked(p)?,
+                    conflicts,
+                    stashes: if collect.stash {
+                        stashes(p)?
+                    } else {
+                        Vec::new()
+                    },
+                    ..Default::default()
+                }
+            }
+        };
+        if collect.last_commit && !matches!(stat.head, Head::Unborn(_)) {
+            stat.last_commit = last_commit(p)?;
+        }
+        if collect.remote || collect.label {
+            for (key, value) in config(p)? {
+                match key.as_str() {
+                    "remote.origin.url" if collect.remote => stat.remote = Some(value),
+                    "branchstat.label" if collect.label => stat.label = Some(value),
+                    _ => {}
+                }
+            }
+        }
+        // State files are cheaper to check directly than through git
+        stat.operation = Operation::detect(&repo::git_dir(p)?);
+        Ok(stat)
+    }
+}
+
+// Gather the work tree state from a single `git status` call. Other local
+// branches still come from `for-each-ref`, which does not scan the work tree.
+// Untracked directories are counted file by file, like `ls-files` does,
+// whatever `status.showUntrackedFiles` says.
+fn porcelain(p: &Path, collect: Collectors) -> Result<BranchStat> {
+    let output = command_stdout(
+        p,
+        &[
+            "status",
+            "--porcelain=v2",
+            "--branch",
+            "--show-stash",
+            "--untracked-files=all",
+            "-z",
+        ],
+    )?;
+    let mut stat = BranchStat {
+        path: p.to_path_buf(),
+        ..Default::default()
+    };
+    let mut current = Tracking::default();
+    let mut stash_count = 0;
+    let mut oid = "";
+    let mut unmerged = Vec::new();
+    let mut records = output.split('\0').filter(|x| !x.is_empty());
+    while let Some(record) = records.next() {
+        let (kind, rest) = record.split_once(' ').unwrap_or((record, ""));
+        match kind {
+            "#" => match rest.split_once(' ') {
+                Some(("branch.oid", commit)) => oid = commit,
+                // Abbreviated by git, which follows `core.abbrev` and
+                // lengthens ids that would be ambiguous
+                Some(("branch.head", "(detached)")) => {
+                    stat.head = Head::Detached {
+                        commit: command_stdout(p, &["rev-parse", "--short", oid])?
+                            .trim()
+                            .to_string(),
+                        tag: exact_tag(p)?,
+                    }
+                }
+                Some(("branch.head", name)) if oid == "(initial)" => {
+                    stat.head = Head::Unborn(name.to_string())
+                }
+                Some(("branch.head", name)) => stat.head = Head::Branch(name.to_string()),
+                Some(("stash", n)) => stash_count = number(n)?,
+                Some(("branch.upstream", name)) => {
+                    // `branch.ab` is missing when the upstream has gone
+                    current.upstream = Some(name.to_string());
+                    current.gone = true;
+                }
+                Some(("branch.ab", ab)) => {
+                    current.gone = false;
+                    for n in ab.split(' ') {
+                        if let Some(n) = n.strip_prefix('+') {
+                            current.ahead = number(n)?;
+                        } else if let Some(n) = n.strip_prefix('-') {
+                            current.behind = number(n)?;
+                        }
+                    }
+                }
+                _ => {}
+            },
+            "1" | "2" => {
+                let xy = rest.as_bytes();
+                match xy.first() {
+                    Some(b'.') | None => {}
+                    Some(&x) => stat.staged.add(x),
+                }
+                if xy.get(1).is_some_and(|&y| y != b'.') {
+                    stat.modified += 1;
+                }
+                // Renames and copies are followed by the original path
+                if kind == "2" {
+                    records.next();
+                }
+            }
+            "u" => {
+                stat.conflicts.add(rest.get(..2).unwrap_or(""));
+                // The path follows the code, submodule state, 4 modes and 3 ids
+                unmerged.extend(rest.splitn(10, ' ').nth(9));
+            }
+            "?" => stat.untracked += 1,
+            _ => {}
+        }
+    }
+
+    // Line counts need a diff, so only ask for one when something changed
+    if collect.diffstat && stat.modified > 0 {
+        stat.unstaged_diff = diffstat(p, false, &unmerged)?;
+    }
+    if collect.diffstat && stat.staged.total() > 0 {
+        stat.staged_diff = diffstat(p, true, &unmerged)?;
+    }
+    if collect.stash && stash_count > 0 {
+        stat.stashes = stashes(p)?;
+    }
+
+    stat.tracking = ahead_behind(p)?;
+    if let Head::Branch(branch) = &stat.head {
+        if let Some(tracking) = stat.tracking.iter_mut().find(|x| &x.name == branch) {
+            current.name = branch.clone();
+            *tracking = current;
+        }
+    }
+    Ok(stat)
+}
+
+/// Resolve what HEAD points at
+pub fn head(p: &Path) -> Result<Head> {
+    let branch = command_optional(p, &["symbolic-ref", "--short", "-q", "HEAD"])?;
+    let commit = command_optional(p, &["rev-parse", "--short", "-q", "--verify", "HEAD"])?;
+    Ok(match (branch, commit) {
+        (Some(branch), Some(_)) => Head::Branch(branch),
+        (Some(branch), None) => Head::Unborn(branch),
+        (None, commit) => Head::Detached {
+            commit: commit.unwrap_or_default(),
+            tag: exact_tag(p)?,
+        },
+    })
+}
+
+// A tag pointing exactly at HEAD, if there is one
+fn exact_tag(p: &Path) -> Result<Option<String>> {
+    command_optional(p, &["describe", "--tags", "--exact-match", "HEAD"])
+}
+
+fn last_commit(p: &Path) -> Result<Option<u64>> {
+    command_optional(p, &["show", "-s", "--format=%ct", "HEAD"])?
+        .map(|x| number(&x))
+        .transpose()
+}
+
+fn ahead_behind(p: &Path) -> Result<Vec<Tracking>> {
+    Ok(command_output(
+        p,
+        &[
+            "for-each-ref",
+            "--format=%(refname:short)%00%(upstream:short)%00%(upstream:track)",
+            "refs/heads",
+        ],
+    )?
+    .par_iter()
+    .filter_map(|x| {
+        let mut fields = x.split('\0');
+        let mut tracking = Tracking {
+            name: fields.next()?.to_string(),
+            upstream: fields.next().filter(|x| !x.is_empty()).map(String::from),
+            ..Default::default()
+        };
+        let track = fields.next().unwrap_or("");
+        for part in track.trim_matches(|c| c == '[' || c == ']').split(", ") {
+            match part.split_once(' ') {
+                Some(("ahead", n)) => tracking.ahead = n.parse().ok()?,
+                Some(("behind", n)) => tracking.behind = n.parse().ok()?,
+                _ => tracking.gone |= part == "gone",
+            }
+        }
+        Some(tracking)
+    })
+    .collect())
+}
+
+// Sum `git diff --numstat` for the work tree, or for the index if `cached`.
+// The `unmerged` paths are left to the conflict counts.
+fn diffstat(p: &Path, cached: bool, unmerged: &[&str]) -> Result<DiffStat> {
+    let mut args = vec!["diff", "--numstat", "-z"];
+    if cached {
+        args.push("--cached");
+    }
+    let output = command_stdout(p, &args)?;
+    let mut stat = DiffStat::default();
+    let mut records = output.split('\0').filter(|x| !x.is_empty());
+    while let Some(record) = records.next() {
+        let mut fields = record.splitn(3, '\t');
+        let (insertions, deletions) = (fields.next(), fields.next());
+        // Renames leave the path empty and put old and new paths after it
+        let path = fields.next().unwrap_or("");
+        if path.is_empty() {
+            records.next();
+            records.next();
+        } else if unmerged.contains(&path) {
+            continue;
+        }
+        stat.files += 1;
+        match (insertions, deletions) {
+            (Some("-"), _) => stat.binary += 1,
+            (Some(i), Some(d)) => {
+                stat.insertions += number::<usize>(i)?;
+                stat.deletions += number::<usize>(d)?;
+            }
+            _ => {}
+        }
+    }
+    Ok(stat)
+}
+
+fn status(p: &Path) -> Result<StagedChanges> {
+    let output = command_stdout(p, &["diff", "--cached", "--name-status", "-M", "-z"])?;
+    let mut staged = StagedChanges::default();
+    let mut records = output.split('\0').filter(|x| !x.is_empty());
+    while let Some(status) = records.next() {
+        let letter = status.as_bytes()[0];
+        // Renames and copies name both paths, everything else one
+        records.next();
+        if letter == b'R' || letter == b'C' {
+            records.next();
+        }
+        // Unmerged paths are counted as conflicts instead
+        if letter != b'U' {
+            staged.add(letter);
+        }
+    }
+    Ok(staged)
+}
+
+// Group the unmerged index entries by path, with a mask of the stages present
+// for classifying the conflict
+fn unmerged(p: &Path) -> Result<Vec<(String, u8)>> {
+    let output = command_stdout(p, &["ls-files", "--unmerged", "-z"])?;
+    let mut stages: Vec<(String, u8)> = Vec::new();
+    for record in output.split('\0').filter(|x| !x.is_empty()) {
+        let (info, path) = record.split_once('\t').unwrap_or((record, ""));
+        let stage: u8 = number(info.rsplit(' ').next().unwrap_or(""))?;
+        match stages.last_mut() {
+            Some((last, mask)) if last == path => *mask |= 1 << stage,
+            _ => stages.push((path.to_string(), 1 << stage)),
+        }
+    }
+    Ok(stages)
+}
+
+fn stashes(p: &Path) -> Result<Vec<Stash>> {
+    command_output(p, &["stash", "list", "--format=%ct %gs"])?
+        .iter()
+        .map(|x| {
+            let (time, message) = x.split_once(' ').unwrap_or((x, ""));
+            Ok(Stash::from_message(number(time)?, message))
+        })
+        .collect()
+}
+
+fn untracked(p: &Path) -> Result<usize> {
+    Ok(command_output(p, &["ls-files", "--others", "--exclude-standard"])?.len())
+}
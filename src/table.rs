@@ -0,0 +1,317 @@
+//! Aligned, terminal-width aware text output.
+//!
+//! Every row is measured before anything is printed, so each column is as
+//! wide as its widest cell. Columns that are empty in every row are left
+//! out. When the table is wider than the terminal, the repository and
+//! branch columns are shortened by cutting out the middle of their text.
+use crate::{
+    format_age, repo_name, BranchStat, ColorChoice, Head, RenderOptions, RepoError, Theme,
+};
+
+/// The columns of a `BranchStat` row, in order
+pub const COLUMNS: usize = 10;
+
+// Columns that can be shortened to fit, and how far
+const SHRINKABLE: [usize; 2] = [0, 1];
+const MIN_WIDTH: usize = 12;
+const GAP: &str = "  ";
+
+/// Text made of differently styled pieces
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Cell {
+    pub parts: Vec<(String, &'static str)>,
+}
+
+impl Cell {
+    pub fn new(text: String, style: &'static str) -> Cell {
+        Cell {
+            parts: vec![(text, style)],
+        }
+    }
+
+    pub fn push(&mut self, text: String, style: &'static str) {
+        self.parts.push((text, style));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.parts.iter().all(|x| x.0.is_empty())
+    }
+
+    pub fn width(&self) -> usize {
+        self.parts.iter().map(|x| x.0.chars().count()).sum()
+    }
+
+    pub fn plain(&self) -> String {
+        self.parts.iter().map(|x| x.0.as_str()).collect()
+    }
+
+    pub fn paint(&self, color: ColorChoice) -> String {
+        self.parts
+            .iter()
+            .map(|(text, style)| match color {
+                ColorChoice::Always => Theme::paint(style, text),
+                _ => text.clone(),
+            })
+            .collect()
+    }
+
+    // Cut the middle out of the text so it fits in `width` columns, keeping
+    // the style of the first part
+    fn truncate_middle(&self, width: usize, ellipsis: &str) -> Cell {
+        let chars: Vec<char> = self.plain().chars().collect();
+        let keep = width.saturating_sub(ellipsis.chars().count());
+        if chars.len() <= width || keep == 0 {
+            return self.clone();
+        }
+        let head = keep - keep / 2;
+        let tail = keep / 2;
+        let text: String = chars[..head]
+            .iter()
+            .chain(ellipsis.chars().collect::<Vec<char>>().iter())
+            .chain(chars[chars.len() - tail..].iter())
+            .collect();
+        Cell::new(text, self.parts.first().map_or("", |x| x.1))
+    }
+}
+
+/// The cells describing `stat`. A clean repository gets a single marker.
+pub fn cells(stat: &BranchStat, opts: &RenderOptions) -> Vec<Cell> {
+    let (theme, sym) = (&opts.theme, &opts.symbols);
+    let mut row = vec![Cell::default(); COLUMNS];
+    row[0] = Cell::new(repo_name(&stat.path, opts.naming), "");
+    let head_style = match stat.head {
+        Head::Detached { .. } => theme.detached,
+        _ => theme.branch,
+    };
+    row[1] = Cell::new(stat.head.to_string(), head_style);
+    // An unfinished operation explains the rest of the line, so it goes first
+    if let Some(op) = &stat.operation {
+        row[2] = Cell::new(format!("*{}*", op), theme.operation);
+    }
+    for tracking in stat.tracking.iter().filter(|x| x.is_diverged()) {
+        let cell = &mut row[3];
+        if !cell.is_empty() {
+            cell.push(" ".to_string(), "");
+        }
+        cell.push(format!("{} ", tracking.name), "");
+        if tracking.gone {
+            cell.push(sym.gone.to_string(), theme.gone);
+            continue;
+        }
+        if tracking.ahead > 0 {
+            cell.push(format!("{}{}", sym.ahead, tracking.ahead), theme.ahead);
+        }
+        if tracking.behind > 0 {
+            cell.push(format!("{}{}", sym.behind, tracking.behind), theme.behind);
+        }
+    }
+    if stat.conflicts.total() > 0 {
+        let text = if opts.compact {
+            format!("{}{}", stat.conflicts.total(), sym.conflicts)
+        } else {
+            format!(
+                "{}{} ({})",
+                stat.conflicts.total(),
+                sym.conflicts,
+                stat.conflicts
+            )
+        };
+        row[4] = Cell::new(text, theme.conflicts);
+    }
+    if stat.modified > 0 {
+        let text = if opts.compact {
+            format!("{}{}", stat.modified, sym.dirty)
+        } else {
+            format!("{}{} ({})", stat.modified, sym.dirty, stat.unstaged_diff)
+        };
+        row[5] = Cell::new(text, theme.dirty);
+    }
+    if stat.staged.total() > 0 {
+        let text = if opts.compact {
+            format!("{}{}", sym.staged, stat.staged.total())
+        } else {
+            format!("{}{} ({})", sym.staged, stat.staged, stat.staged_diff)
+        };
+        row[6] = Cell::new(text, theme.staged);
+    }
+    if stat.untracked > 0 {
+        row[7] = Cell::new(
+            format!("{}{}", stat.untracked, sym.untracked),
+            theme.untracked,
+        );
+    }
+    if let Some(age) = stat.oldest_stash_age() {
+        let text = if opts.compact {
+            format!("{}{}", sym.stash, stat.stashes.len())
+        } else {
+            format!("{}{} ({})", sym.stash, stat.stashes.len(), format_age(age))
+        };
+        row[8] = Cell::new(text, theme.stash);
+    }
+    if !stat.mismatches.is_empty() {
+        let text: Vec<String> = stat.mismatches.iter().map(|x| x.to_string()).collect();
+        row[9] = Cell::new(text.join(", "), theme.error);
+    }
+
+    if row[2..].iter().all(Cell::is_empty) {
+        row[2] = Cell::new(sym.clean.to_string(), theme.clean);
+    }
+    row
+}
+
+enum Row {
+    Cells(Vec<Cell>),
+    /// Leading cells, then a message that spans the remaining columns
+    Spanning(Vec<Cell>, Cell),
+    /// A line of its own, ignoring the columns, set apart from any rows above
+    Heading(Cell),
+}
+
+/// Rows of cells, printed with every column aligned
+#[derive(Default)]
+pub struct Table {
+    rows: Vec<Row>,
+}
+
+impl Table {
+    pub fn push(&mut self, cells: Vec<Cell>) {
+        self.rows.push(Row::Cells(cells));
+    }
+
+    /// Add a repository that could not be inspected, named like the others
+    pub fn push_error(&mut self, err: &RepoError, opts: &RenderOptions) {
+        self.rows.push(Row::Spanning(
+            vec![
+                Cell::new(repo_name(&err.path, opts.naming), ""),
+                Cell::new("error".to_string(), opts.theme.error),
+            ],
+            Cell::new(err.to_string(), opts.theme.error),
+        ));
+    }
+
+    /// Start a group of rows under `title`
+    pub fn push_heading(&mut self, title: Cell) {
+        self.rows.push(Row::Heading(title));
+    }
+
+    /// Add a line summarising the rows above, aligned with the second column
+    pub fn push_total(&mut self, total: Cell) {
+        self.rows.push(Row::Spanning(vec![Cell::default()], total));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    /// Lay the table out, fitting it into `width` columns if given
+    pub fn render(&self, opts: &RenderOptions, width: Option<usize>) -> String {
+        let columns = self
+            .rows
+            .iter()
+            .map(|row| match row {
+                Row::Cells(cells) | Row::Spanning(cells, _) => cells.len(),
+                Row::Heading(_) => 0,
+            })
+            .max()
+            .unwrap_or(0);
+        let mut widths = vec![0; columns];
+        for row in &self.rows {
+            let cells = match row {
+                Row::Cells(cells) | Row::Spanning(cells, _) => cells,
+                Row::Heading(_) => continue,
+            };
+            for (i, cell) in cells.iter().enumerate() {
+                widths[i] = widths[i].max(cell.width());
+            }
+        }
+        // Empty columns take no space at all
+        let shown: Vec<usize> = (0..columns).filter(|&i| widths[i] > 0).collect();
+
+        if let Some(width) = width {
+            let total: usize = shown.iter().map(|&i| widths[i]).sum::<usize>()
+                + GAP.len() * shown.len().saturating_sub(1);
+            let mut over = total.saturating_sub(width);
+            for &i in SHRINKABLE.iter().filter(|&&i| i < columns) {
+                let cut = over.min(widths[i].saturating_sub(MIN_WIDTH));
+                widths[i] -= cut;
+                over -= cut;
+            }
+        }
+
+        let mut out = String::new();
+        for row in &self.rows {
+            let (cells, message) = match row {
+                Row::Cells(cells) => (cells, None),
+                Row::Spanning(cells, message) => (cells, Some(message)),
+                Row::Heading(title) => {
+                    if !out.is_empty() {
+                        out.push('\n');
+                    }
+                    out.push_str(&title.paint(opts.color));
+                    out.push('\n');
+                    continue;
+                }
+            };
+            let mut line = String::new();
+            let last = if message.is_some() {
+                cells.len()
+            } else {
+                // Trailing empty cells would only leave trailing spaces
+                shown
+                    .iter()
+                    .rposition(|&i| cells.get(i).is_some_and(|x| !x.is_empty()))
+                    .map_or(0, |x| x + 1)
+            };
+            for (n, &i) in shown.iter().enumerate().take(last) {
+                let cell = match cells.get(i) {
+                    Some(cell) if cell.width() > widths[i] => {
+                        cell.truncate_middle(widths[i], opts.symbols.ellipsis)
+                    }
+                    Some(cell) => cell.clone(),
+                    None => Cell::default(),
+                };
+                if n > 0 {
+                    line.push_str(GAP);
+                }
+                line.push_str(&cell.paint(opts.color));
+                let is_last = n + 1 == last && message.is_none();
+                if !is_last {
+                    line.push_str(&" ".repeat(widths[i] - cell.width()));
+                }
+            }
+            if let Some(message) = message {
+                line.push_str(GAP);
+                line.push_str(&message.paint(opts.color));
+            }
+            out.push_str(&line);
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/// The width of the terminal on stdout, or `None` when it is not one.
+/// `COLUMNS` takes precedence, as it does for most tools.
+pub fn terminal_width() -> Option<usize> {
+    if let Some(columns) = std::env::var("COLUMNS").ok().and_then(|x| x.parse().ok()) {
+        return Some(columns);
+    }
+    ioctl_width()
+}
+
+#[cfg(unix)]
+fn ioctl_width() -> Option<usize> {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    // SAFETY: TIOCGWINSZ only writes a `winsize` through the pointer
+    let ok = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0;
+    if ok && size.ws_col > 0 {
+        Some(usize::from(size.ws_col))
+    } else {
+        None
+    }
+}
+
+#[cfg(not(unix))]
+fn ioctl_width() -> Option<usize> {
+    None
+}
@@ -0,0 +1,376 @@
+//! A small TOML reader for configuration files.
+//!
+//! Tables, arrays of tables, dotted and quoted keys, and string, integer,
+//! boolean and array values are supported. Floats, dates, inline tables and
+//! multi-line strings are not.
+use anyhow::{anyhow, Result};
+
+/// A parsed TOML value
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    Array(Vec<Value>),
+    Table(Table),
+}
+
+/// Keys and values in the order they were written
+pub type Table = Vec<(String, Value)>;
+
+impl Value {
+    /// The name of the value's type, for error messages
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "a string",
+            Value::Integer(_) => "an integer",
+            Value::Boolean(_) => "a boolean",
+            Value::Array(_) => "an array",
+            Value::Table(_) => "a table",
+        }
+    }
+}
+
+/// Parse a whole document into its root table
+pub fn parse(text: &str) -> Result<Table> {
+    let mut parser = Parser {
+        chars: text.chars().collect(),
+        pos: 0,
+        line: 1,
+    };
+    let mut root = Table::new();
+    // The path of the table that keys are currently added to
+    let mut current: Vec<String> = Vec::new();
+    loop {
+        parser.skip_blank();
+        match parser.peek() {
+            None => break,
+            Some('\n') => {
+                parser.next();
+                continue;
+            }
+            Some('[') => {
+                parser.next();
+                let array = parser.eat('[');
+                parser.skip_space();
+                let path = parser.key()?;
+                parser.skip_space();
+                if !parser.eat(']') || (array && !parser.eat(']')) {
+                    return Err(parser.error("expected ']' after the table name"));
+                }
+                let line = parser.line;
+                if array {
+                    let (last, parent) = path.split_last().expect("keys are never empty");
+                    let parent = table_at(&mut root, parent, line)?;
+                    match parent.iter_mut().find(|x| &x.0 == last) {
+                        Some((_, Value::Array(items))) => items.push(Value::Table(Table::new())),
+                        Some(_) => return Err(parser.error(&format!("'{}' is not an array", last))),
+                        None => parent
+                            .push((last.clone(), Value::Array(vec![Value::Table(Table::new())]))),
+                    }
+                } else if !table_at(&mut root, &path, line)?.is_empty() {
+                    return Err(
+                        parser.error(&format!("table '{}' is defined twice", path.join(".")))
+                    );
+                }
+                current = path;
+            }
+            Some(_) => {
+                let path = parser.key()?;
+                parser.skip_space();
+                if !parser.eat('=') {
+                    return Err(parser.error("expected '=' after the key"));
+                }
+                parser.skip_space();
+                let value = parser.value()?;
+                let line = parser.line;
+                let table = table_at(&mut root, &current, line)?;
+                let (last, parent) = path.split_last().expect("keys are never empty");
+                let table = table_at(table, parent, line)?;
+                if table.iter().any(|x| &x.0 == last) {
+                    return Err(parser.error(&format!("'{}' is set twice", path.join("."))));
+                }
+                table.push((last.clone(), value));
+            }
+        }
+        parser.skip_blank();
+        match parser.next() {
+            None | Some('\n') => {}
+            Some(c) => return Err(parser.error(&format!("unexpected '{}'", c))),
+        }
+    }
+    Ok(root)
+}
+
+// The table at `path` beneath `table`, created if missing. A path through an
+// array of tables refers to its last element.
+fn table_at<'a>(mut table: &'a mut Table, path: &[String], line: usize) -> Result<&'a mut Table> {
+    for key in path {
+        let n = match table.iter().position(|x| &x.0 == key) {
+            Some(n) => n,
+            None => {
+                table.push((key.clone(), Value::Table(Table::new())));
+                table.len() - 1
+            }
+        };
+        table = match &mut table[n].1 {
+            Value::Table(inner) => inner,
+            Value::Array(items) => match items.last_mut() {
+                Some(Value::Table(inner)) => inner,
+                _ => return Err(anyhow!("line {}: '{}' is not a table", line, key)),
+            },
+            _ => return Err(anyhow!("line {}: '{}' is not a table", line, key)),
+        };
+    }
+    Ok(table)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn error(&self, message: &str) -> anyhow::Error {
+        anyhow!("line {}: {}", self.line, message)
+    }
+
+    fn skip_space(&mut self) {
+        while matches!(self.peek(), Some(' ') | Some('\t') | Some('\r')) {
+            self.next();
+        }
+    }
+
+    // Spaces and a comment, up to the end of the line
+    fn skip_blank(&mut self) {
+        self.skip_space();
+        if self.peek() == Some('#') {
+            while !matches!(self.peek(), None | Some('\n')) {
+                self.next();
+            }
+        }
+    }
+
+    // Blank lines and comments, which may appear between array items
+    fn skip_lines(&mut self) {
+        loop {
+            self.skip_blank();
+            if !self.eat('\n') {
+                break;
+            }
+        }
+    }
+
+    // A dotted key such as `labels."my repo"`
+    fn key(&mut self) -> Result<Vec<String>> {
+        let mut path = Vec::new();
+        loop {
+            self.skip_space();
+            let part = match self.peek() {
+                Some('"') | Some('\'') => self.string()?,
+                _ => {
+                    let start = self.pos;
+                    while self
+                        .peek()
+                        .is_some_and(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+                    {
+                        self.next();
+                    }
+                    if self.pos == start {
+                        return Err(self.error("expected a key"));
+                    }
+                    self.chars[start..self.pos].iter().collect()
+                }
+            };
+            path.push(part);
+            self.skip_space();
+            if !self.eat('.') {
+                return Ok(path);
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        match self.peek() {
+            Some('"') | Some('\'') => Ok(Value::String(self.string()?)),
+            Some('[') => {
+                self.next();
+                let mut items = Vec::new();
+                loop {
+                    self.skip_lines();
+                    if self.eat(']') {
+                        return Ok(Value::Array(items));
+                    }
+                    items.push(self.value()?);
+                    self.skip_lines();
+                    if self.eat(']') {
+                        return Ok(Value::Array(items));
+                    }
+                    if !self.eat(',') {
+                        return Err(self.error("expected ',' or ']' in array"));
+                    }
+                }
+            }
+            _ => {
+                let start = self.pos;
+                while self
+                    .peek()
+                    .is_some_and(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '+')
+                {
+                    self.next();
+                }
+                let word: String = self.chars[start..self.pos].iter().collect();
+                match word.as_str() {
+                    "true" => Ok(Value::Boolean(true)),
+                    "false" => Ok(Value::Boolean(false)),
+                    "" => Err(self.error("expected a value")),
+                    _ => word
+                        .replace('_', "")
+                        .parse()
+                        .map(Value::Integer)
+                        .map_err(|_| self.error(&format!("unsupported value '{}'", word))),
+                }
+            }
+        }
+    }
+
+    // A basic string with escapes, or a literal string in single quotes
+    fn string(&mut self) -> Result<String> {
+        let quote = self.next().unwrap_or('"');
+        let mut s = String::new();
+        while let Some(c) = self.peek().filter(|&c| c != '\n') {
+            self.next();
+            match c {
+                c if c == quote => return Ok(s),
+                '\\' if quote == '"' => s.push(self.escape()?),
+                c => s.push(c),
+            }
+        }
+        Err(self.error("unterminated string"))
+    }
+
+    fn escape(&mut self) -> Result<char> {
+        Ok(match self.next() {
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('r') => '\r',
+            Some('"') => '"',
+            Some('\\') => '\\',
+            Some(u) if u == 'u' || u == 'U' => {
+                let len = if u == 'u' { 4 } else { 8 };
+                let hex: String = (0..len).filter_map(|_| self.next()).collect();
+                u32::from_str_radix(&hex, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| self.error(&format!("invalid escape '\\{}{}'", u, hex)))?
+            }
+            Some(c) => return Err(self.error(&format!("invalid escape '\\{}'", c))),
+            None => return Err(self.error("unterminated string")),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(s: &str) -> Value {
+        Value::String(s.to_string())
+    }
+
+    #[test]
+    fn dotted_and_quoted_keys() {
+        let table = parse("a.b = 1\n[labels]\n\"~/work/*\" = 'work'\nx.'y z' = true\n").unwrap();
+        assert_eq!(
+            table,
+            vec![
+                (
+                    "a".to_string(),
+                    Value::Table(vec![("b".to_string(), Value::Integer(1))])
+                ),
+                (
+                    "labels".to_string(),
+                    Value::Table(vec![
+                        ("~/work/*".to_string(), string("work")),
+                        (
+                            "x".to_string(),
+                            Value::Table(vec![("y z".to_string(), Value::Boolean(true))])
+                        ),
+                    ])
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn arrays_of_tables() {
+        let text = "[[repo]]\npath = \"a\"\n\n[[repo]]\npath = \"b\"\ntags = [\"x\"]\n";
+        let table = parse(text).unwrap();
+        let repos = vec![
+            Value::Table(vec![("path".to_string(), string("a"))]),
+            Value::Table(vec![
+                ("path".to_string(), string("b")),
+                ("tags".to_string(), Value::Array(vec![string("x")])),
+            ]),
+        ];
+        assert_eq!(table, vec![("repo".to_string(), Value::Array(repos))]);
+    }
+
+    #[test]
+    fn strings() {
+        let table = parse(
+            r#"basic = "tab\there \"quoted\" \u00e9"
+literal = 'C:\path\no "escapes"'
+"#,
+        )
+        .unwrap();
+        assert_eq!(table[0].1, string("tab\there \"quoted\" é"));
+        assert_eq!(table[1].1, string(r#"C:\path\no "escapes""#));
+    }
+
+    #[test]
+    fn multi_line_arrays_with_comments() {
+        let text =
+            "roots = [ # where to look\n  \"~/code\",\n\n  # not archived\n  \"~/work\",\n]\n";
+        let table = parse(text).unwrap();
+        let roots = Value::Array(vec![string("~/code"), string("~/work")]);
+        assert_eq!(table, vec![("roots".to_string(), roots)]);
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        let error = |text: &str| parse(text).unwrap_err().to_string();
+        assert_eq!(error("a = 1\nb = \"open\n"), "line 2: unterminated string");
+        assert_eq!(error("a = 1\na = 2\n"), "line 2: 'a' is set twice");
+        assert_eq!(
+            error("[t]\na = 1\n[t]\n"),
+            "line 3: table 't' is defined twice"
+        );
+        assert_eq!(error("a = 1.5\n"), "line 1: unexpected '.'");
+        assert_eq!(error("a = 1\n[[a]]\n"), "line 2: 'a' is not an array");
+        assert_eq!(error("key\n"), "line 1: expected '=' after the key");
+    }
+}
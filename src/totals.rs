@@ -0,0 +1,125 @@
+//! Counts across many repositories.
+use crate::filter::Filter;
+use crate::{BranchStat, RepoError};
+use std::fmt;
+use std::iter::FromIterator;
+
+/// How many repositories are in each state, and the work they hold
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Totals {
+    pub repos: usize,
+    pub clean: usize,
+    /// Repositories with changed or conflicted files, or an unfinished
+    /// operation
+    pub dirty: usize,
+    pub ahead: usize,
+    pub behind: usize,
+    pub stashed: usize,
+    pub errors: usize,
+    /// Commits ahead of their upstream, across all local branches
+    pub unpushed: usize,
+    /// Files with unstaged changes
+    pub modified: usize,
+    pub untracked: usize,
+    /// Repositories found beneath the scanned paths that the manifest does
+    /// not list. They are not inspected, so they are not among `repos` and
+    /// are not counted from the results.
+    pub unlisted: usize,
+}
+
+impl Totals {
+    pub fn add(&mut self, result: &Result<BranchStat, RepoError>) {
+        self.repos += 1;
+        let stat = match result {
+            Ok(stat) => stat,
+            Err(_) => {
+                self.errors += 1;
+                return;
+            }
+        };
+        let count = |filter: Filter| usize::from(filter.matches(stat));
+        self.clean += usize::from(stat.is_clean());
+        self.dirty += usize::from(stat.is_dirty());
+        self.ahead += count(Filter::Ahead);
+        self.behind += count(Filter::Behind);
+        self.stashed += count(Filter::Stash);
+        self.unpushed += stat.tracking.iter().map(|x| x.ahead).sum::<usize>();
+        self.modified += stat.modified;
+        self.untracked += stat.untracked;
+    }
+}
+
+impl Totals {
+    /// Every count, zero or not, as the lines of a footer, then the
+    /// repositories the manifest does not list if there are any
+    pub fn summary(&self) -> Vec<String> {
+        let mut lines = vec![
+            format!(
+                "{} scanned: {} clean, {} dirty, {} ahead, {} behind, {} with stashes, {} failed",
+                self.repos,
+                self.clean,
+                self.dirty,
+                self.ahead,
+                self.behind,
+                self.stashed,
+                self.errors
+            ),
+            format!(
+                "{} unpushed {}, {} modified and {} untracked {}",
+                self.unpushed,
+                plural(self.unpushed, "commit", "commits"),
+                self.modified,
+                self.untracked,
+                plural(self.untracked, "file", "files")
+            ),
+        ];
+        if self.unlisted > 0 {
+            lines.push(format!("{} not in the manifest", self.unlisted));
+        }
+        lines
+    }
+}
+
+fn plural(n: usize, one: &'static str, many: &'static str) -> &'static str {
+    if n == 1 {
+        one
+    } else {
+        many
+    }
+}
+
+impl<'a> FromIterator<&'a Result<BranchStat, RepoError>> for Totals {
+    fn from_iter<I: IntoIterator<Item = &'a Result<BranchStat, RepoError>>>(iter: I) -> Totals {
+        let mut totals = Totals::default();
+        for result in iter {
+            totals.add(result);
+        }
+        totals
+    }
+}
+
+/// A single line such as `4 repositories: 2 dirty, 1 ahead`, listing only
+/// the states some repository is in
+impl fmt::Display for Totals {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let noun = plural(self.repos, "repository", "repositories");
+        write!(f, "{} {}", self.repos, noun)?;
+        let parts = [
+            (self.dirty, "dirty"),
+            (self.ahead, "ahead"),
+            (self.behind, "behind"),
+            (self.stashed, "with stashes"),
+            (self.errors, "failed"),
+            (self.unlisted, "unlisted"),
+        ];
+        let parts: Vec<String> = parts
+            .iter()
+            .filter(|(n, _)| *n > 0)
+            .map(|(n, what)| format!("{} {}", n, what))
+            .collect();
+        if !parts.is_empty() {
+            write!(f, ": {}", parts.join(", "))?;
+        }
+        Ok(())
+    }
+}
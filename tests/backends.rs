@@ -0,0 +1,331 @@
+//! Every backend and collector should report the same state for the same
+//! repository.
+#[cfg(feature = "native")]
+use git_branchstat::Native;
+mod common;
+
+use common::Fixture;
+use git_branchstat::{
+    Backend, BranchStat, Collector, Collectors, DiffStat, Head, Operation, StagedChanges,
+    Subprocess,
+};
+use std::fs;
+use std::path::Path;
+
+// Every collector on, so every field is compared
+const EVERY: Collectors = Collectors {
+    stash: true,
+    diffstat: true,
+    last_commit: true,
+    remote: true,
+    label: true,
+};
+
+fn collect(p: &Path, collector: Collector, collectors: Collectors) -> BranchStat {
+    let backend = Subprocess {
+        collector,
+        collectors,
+    };
+    backend.branchstat(p).unwrap()
+}
+
+// Collect with every backend and collector, check they agree and return
+// the result
+fn agreed(fixture: &Fixture) -> BranchStat {
+    agreed_with(fixture, EVERY)
+}
+
+fn agreed_with(fixture: &Fixture, collectors: Collectors) -> BranchStat {
+    let stat = collect(&fixture.dir, Collector::Porcelain, collectors);
+    let legacy = collect(&fixture.dir, Collector::Legacy, collectors);
+    assert_eq!(stat, legacy, "legacy");
+    #[cfg(feature = "native")]
+    {
+        let native = Native { collectors }.branchstat(&fixture.dir).unwrap();
+        assert_eq!(stat, native, "native");
+    }
+    stat
+}
+
+#[test]
+fn untracked_files_in_directories() {
+    let fixture = Fixture::new("untracked");
+    fixture.write("README", "hello\n");
+    fixture.commit("First");
+    fixture.write("new/a", "a\n");
+    fixture.write("new/b", "b\n");
+    fixture.write("new/deeper/c", "c\n");
+    assert_eq!(agreed(&fixture).untracked, 3);
+    fixture.git(&["config", "status.showUntrackedFiles", "no"]);
+    assert_eq!(agreed(&fixture).untracked, 3);
+}
+
+#[test]
+fn staged_changes() {
+    let fixture = Fixture::new("staged");
+    let long: String = (1..=100).map(|x| format!("line {}\n", x)).collect();
+    fixture.write("kept", "a\nb\n");
+    fixture.write("gone", "x\n");
+    fixture.write("moved", &long);
+    fixture.commit("First");
+    fixture.write("kept", "a\nc\n");
+    fixture.write("new", "new\n");
+    fixture.git(&["rm", "-q", "gone"]);
+    // Similar contents, not identical, are still a rename
+    fixture.git(&["mv", "moved", "renamed"]);
+    fixture.write("renamed", &long.replacen("line 1\n", "line one\n", 1));
+    fixture.git(&["add", "-A"]);
+    let stat = agreed(&fixture);
+    let expected = StagedChanges {
+        added: 1,
+        modified: 1,
+        deleted: 1,
+        renamed: 1,
+        copied: 0,
+    };
+    assert_eq!(stat.staged, expected);
+    assert_eq!(stat.staged_diff.files, 4);
+}
+
+#[test]
+fn unstaged_changes() {
+    let fixture = Fixture::new("unstaged");
+    fixture.write("text", "a\nb\n");
+    fixture.write("binary", "\0\x01");
+    fixture.write("deleted", "d\n");
+    fixture.commit("First");
+    fixture.write("text", "a\nb\nc\n");
+    fixture.write("binary", "\0\x02");
+    fs::remove_file(fixture.dir.join("deleted")).unwrap();
+    let stat = agreed(&fixture);
+    assert_eq!(stat.modified, 3);
+    assert_eq!(stat.unstaged_diff.binary, 1);
+    assert_eq!(stat.unstaged_diff.insertions, 1);
+    assert_eq!(stat.unstaged_diff.deletions, 1);
+}
+
+#[test]
+fn intent_to_add() {
+    let fixture = Fixture::new("intent");
+    fixture.write("README", "hello\n");
+    fixture.commit("First");
+    fixture.write("later", "later\n");
+    fixture.git(&["add", "-N", "later"]);
+    let stat = agreed(&fixture);
+    assert_eq!(stat.staged.total(), 0);
+    assert_eq!(stat.untracked, 0);
+}
+
+#[test]
+fn sparse_checkout() {
+    let fixture = Fixture::new("sparse");
+    fixture.write("keep/a", "a\n");
+    fixture.write("drop/b", "b\n");
+    fixture.commit("First");
+    fixture.git(&["sparse-checkout", "set", "keep"]);
+    assert!(agreed(&fixture).is_clean());
+}
+
+#[test]
+fn ignored_files() {
+    let fixture = Fixture::new("ignored");
+    fixture.write(".gitignore", "*.log\n!keep.log\nbuild/\n");
+    fixture.commit("First");
+    fixture.write("debug.log", "x\n");
+    fixture.write("build/out", "x\n");
+    fixture.write("src/keep.log", "x\n");
+    fixture.write("src/notes", "x\n");
+    fixture.write(".git/info/exclude", "local\n");
+    fixture.write("local", "x\n");
+    assert_eq!(agreed(&fixture).untracked, 2);
+}
+
+#[test]
+fn branches_and_upstreams() {
+    let upstream = Fixture::new("upstream-origin");
+    upstream.write("README", "hello\n");
+    upstream.commit("First");
+    upstream.git(&["branch", "old"]);
+    let fixture = Fixture::new("upstream");
+    fixture.git(&["remote", "add", "origin", upstream.dir.to_str().unwrap()]);
+    fixture.git(&["fetch", "-q", "origin"]);
+    fixture.git(&["checkout", "-q", "-b", "main", "--track", "origin/main"]);
+    fixture.git(&["branch", "-q", "--track", "old", "origin/old"]);
+    fixture.git(&["branch", "-q", "local"]);
+    fixture.write("README", "changed\n");
+    fixture.commit("Ahead");
+    upstream.write("README", "upstream\n");
+    upstream.commit("Behind");
+    fixture.git(&["fetch", "-q", "origin"]);
+    fixture.git(&["update-ref", "-d", "refs/remotes/origin/old"]);
+    let stat = agreed(&fixture);
+    let summary: Vec<_> = stat
+        .tracking
+        .iter()
+        .map(|x| {
+            (
+                x.name.as_str(),
+                x.upstream.as_deref(),
+                x.ahead,
+                x.behind,
+                x.gone,
+            )
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            ("local", None, 0, 0, false),
+            ("main", Some("origin/main"), 1, 1, false),
+            ("old", Some("origin/old"), 0, 0, true),
+        ]
+    );
+}
+
+#[test]
+fn heads() {
+    let fixture = Fixture::new("heads");
+    assert_eq!(agreed(&fixture).head, Head::Unborn("main".to_string()));
+    fixture.write("README", "hello\n");
+    fixture.commit("First");
+    fixture.git(&["tag", "-a", "-m", "Release", "v1"]);
+    fixture.git(&["checkout", "-q", "--detach"]);
+    let stat = agreed(&fixture);
+    assert!(matches!(stat.head, Head::Detached { tag: Some(ref tag), .. } if tag == "v1"));
+    assert!(stat.last_commit.is_some());
+    fixture.git(&["config", "core.abbrev", "12"]);
+    let stat = agreed(&fixture);
+    assert!(matches!(stat.head, Head::Detached { ref commit, .. } if commit.len() == 12));
+}
+
+#[test]
+fn stashes_and_config() {
+    let fixture = Fixture::new("stashes");
+    fixture.write("README", "hello\n");
+    fixture.commit("First");
+    fixture.write("README", "one\n");
+    fixture.git(&["stash", "-q"]);
+    fixture.git(&["checkout", "-q", "-b", "other"]);
+    fixture.write("README", "two\n");
+    fixture.git(&["stash", "-q"]);
+    fixture.git(&["remote", "add", "origin", "git@example.com:me/stashes.git"]);
+    // Included files and inline comments are read like git reads them
+    fixture.write(
+        ".git/extra",
+        "[branchstat]\n\tlabel = work # for grouping\n",
+    );
+    fixture.git(&["config", "include.path", "extra"]);
+    let stat = agreed(&fixture);
+    let branches: Vec<_> = stat.stashes.iter().map(|x| x.branch.as_deref()).collect();
+    assert_eq!(branches, vec![Some("other"), Some("main")]);
+    assert_eq!(
+        stat.remote.as_deref(),
+        Some("git@example.com:me/stashes.git")
+    );
+    assert_eq!(stat.label.as_deref(), Some("work"));
+}
+
+#[test]
+fn conflicts() {
+    let fixture = Fixture::new("conflicts");
+    fixture.write("both", "base\n");
+    fixture.write("theirs", "base\n");
+    fixture.commit("First");
+    fixture.git(&["checkout", "-q", "-b", "other"]);
+    fixture.write("both", "other\n");
+    fixture.write("theirs", "other\n");
+    fixture.commit("Other");
+    fixture.git(&["checkout", "-q", "main"]);
+    fixture.write("both", "main\n");
+    fixture.commit("Main");
+    assert!(!fixture.run(&["merge", "-q", "other"]).status.success());
+    let stat = agreed(&fixture);
+    assert_eq!(stat.conflicts.both_modified, 1);
+    assert_eq!(stat.conflicts.total(), 1);
+    assert_eq!(stat.modified, 0);
+    assert_eq!(stat.unstaged_diff.files, 0);
+    // Only the cleanly merged file is staged
+    assert_eq!(stat.staged.modified, 1);
+    assert_eq!(stat.staged.total(), 1);
+    assert_eq!(stat.staged_diff.files, 1);
+}
+
+#[test]
+fn collectors_turned_off() {
+    let fixture = Fixture::new("collectors");
+    fixture.write("README", "hello\n");
+    fixture.commit("First");
+    fixture.write("README", "stashed\n");
+    fixture.git(&["stash", "-q"]);
+    fixture.git(&[
+        "remote",
+        "add",
+        "origin",
+        "git@example.com:me/collectors.git",
+    ]);
+    fixture.git(&["config", "branchstat.label", "work"]);
+    fixture.write("README", "changed\n");
+    fixture.write("new", "new\n");
+    fixture.git(&["add", "new"]);
+    let full = agreed(&fixture);
+    let off = Collectors {
+        stash: false,
+        diffstat: false,
+        last_commit: false,
+        remote: false,
+        label: false,
+    };
+    let stat = agreed_with(&fixture, off);
+    let expected = BranchStat {
+        unstaged_diff: DiffStat::default(),
+        staged_diff: DiffStat::default(),
+        stashes: Vec::new(),
+        last_commit: None,
+        remote: None,
+        label: None,
+        ..full
+    };
+    assert_eq!(stat, expected);
+    assert_eq!((stat.modified, stat.staged.added), (1, 1));
+}
+
+#[test]
+fn operations() {
+    let fixture = Fixture::new("operations");
+    fixture.write("file", "base\n");
+    fixture.commit("First");
+    fixture.git(&["checkout", "-q", "-b", "topic"]);
+    for (i, text) in ["one\n", "two\n", "three\n"].iter().enumerate() {
+        fixture.write("file", text);
+        fixture.commit(&format!("Topic {}", i + 1));
+    }
+    fixture.git(&["checkout", "-q", "main"]);
+    fixture.write("file", "main\n");
+    fixture.commit("Main");
+    let stopped = |args: &[&str], expected: &str| {
+        assert!(!fixture.run(args).status.success(), "{}", args.join(" "));
+        let op = agreed(&fixture).operation.map(|x| x.to_string());
+        assert_eq!(op.as_deref(), Some(expected), "{}", args.join(" "));
+    };
+
+    // A plain rebase with the merge backend leaves the same state behind as
+    // an interactive one
+    fixture.git(&["checkout", "-q", "topic"]);
+    stopped(&["rebase", "main"], "REBASE 1/3");
+    fixture.git(&["rebase", "--abort"]);
+    stopped(&["rebase", "--apply", "main"], "REBASE 1/3");
+    fixture.git(&["rebase", "--abort"]);
+    fixture.git(&["checkout", "-q", "main"]);
+    stopped(&["merge", "-q", "topic"], "MERGE");
+    fixture.git(&["merge", "--abort"]);
+    stopped(&["cherry-pick", "topic"], "CHERRY-PICK");
+    fixture.git(&["cherry-pick", "--abort"]);
+    fixture.write("file", "again\n");
+    fixture.commit("Again");
+    stopped(&["revert", "--no-edit", "HEAD~1"], "REVERT");
+    fixture.git(&["revert", "--abort"]);
+    fixture.git(&["bisect", "start"]);
+    assert_eq!(agreed(&fixture).operation, Some(Operation::Bisect));
+    fixture.git(&["bisect", "reset"]);
+    assert_eq!(agreed(&fixture).operation, None);
+}
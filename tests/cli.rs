@@ -0,0 +1,219 @@
+//! Running the binary against fixture repositories.
+mod common;
+
+use common::Fixture;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+
+// Run the binary without any global config and return what it printed
+fn run(args: &[&str], env: &[(&str, &Path)]) -> String {
+    String::from_utf8_lossy(&output(args, env).stdout).into_owned()
+}
+
+fn output(args: &[&str], env: &[(&str, &Path)]) -> Output {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_git-branchstat"));
+    command.args(args).env(
+        "XDG_CONFIG_HOME",
+        Path::new(env!("CARGO_TARGET_TMPDIR")).join("no-config"),
+    );
+    for (name, value) in env {
+        command.env(name, value);
+    }
+    command.output().unwrap()
+}
+
+// Run with `--format ndjson` and return one line per repository
+fn ndjson(args: &[&str], env: &[(&str, &Path)]) -> Vec<String> {
+    let args: Vec<&str> = vec!["--format", "ndjson", "--all"]
+        .into_iter()
+        .chain(args.iter().copied())
+        .collect();
+    run(&args, env).lines().map(String::from).collect()
+}
+
+#[test]
+fn git_dir_applies_only_to_its_work_tree() {
+    let a = Fixture::new("git-dir/a");
+    a.write("file", "a\n");
+    a.commit("First");
+    a.write("file", "changed\n");
+    let b = Fixture::new("git-dir/b");
+    b.write("file", "b\n");
+    b.commit("First");
+    let u = Fixture::new("git-dir/u");
+    let paths: Vec<&str> = vec![&a.dir, &b.dir, &u.dir]
+        .into_iter()
+        .map(|x| x.to_str().unwrap())
+        .collect();
+    let git_dir = a.dir.join(".git");
+    let lines = ndjson(&paths, &[("GIT_DIR", &git_dir), ("GIT_WORK_TREE", &a.dir)]);
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].contains("\"name\":\"a\"") && lines[0].contains("\"modified\":1"));
+    assert!(lines[1].contains("\"name\":\"b\"") && lines[1].contains("\"modified\":0"));
+    assert!(lines[2].contains("\"name\":\"u\"") && lines[2].contains("\"kind\":\"unborn\""));
+}
+
+#[test]
+fn collectors_from_a_root_file() {
+    let fixture = Fixture::new("collectors/repo");
+    fixture.write("README", "hello\n");
+    fixture.commit("First");
+    fixture.write("README", "stashed\n");
+    fixture.git(&["stash", "-q"]);
+    let root = fixture.dir.parent().unwrap();
+    std::fs::write(
+        root.join(".branchstat.toml"),
+        "[collectors]\nstash = false\n",
+    )
+    .unwrap();
+    let root = root.to_str().unwrap();
+    let show = run(&["config", "show", root], &[]);
+    let line = |start: &str| show.lines().find(|x| x.starts_with(start)).unwrap();
+    assert!(
+        line("stash = false").ends_with(".branchstat.toml"),
+        "{}",
+        show
+    );
+    assert!(line("diffstat = true").ends_with("# default"), "{}", show);
+    let lines = ndjson(&[root], &[]);
+    assert_eq!(lines.len(), 1);
+    assert!(lines[0].contains("\"stashes\":[]"), "{}", lines[0]);
+}
+
+#[test]
+fn differences_from_the_manifest() {
+    let fixture = Fixture::new("manifest/repo");
+    fixture.write("README", "hello\n");
+    fixture.commit("First");
+    fixture.git(&["remote", "add", "origin", "git@example.com:me/repo.git"]);
+    let manifest = fixture.dir.parent().unwrap().join("manifest.toml");
+    let write = |entry: &str| {
+        std::fs::write(&manifest, format!("[[repo]]\npath = \"repo\"\n{}", entry)).unwrap();
+    };
+    let manifest = manifest.to_str().unwrap();
+
+    // The same repository over https matches
+    write("remote = \"https://example.com/me/repo\"\ndefault_branch = \"main\"\n");
+    let lines = ndjson(&["--manifest", manifest], &[]);
+    assert_eq!(lines.len(), 1);
+    assert!(lines[0].contains("\"mismatches\":[]"), "{}", lines[0]);
+    assert_eq!(run(&["--manifest", manifest], &[]), "");
+
+    write("remote = \"git@example.com:team/repo.git\"\ndefault_branch = \"trunk\"\n");
+    let lines = ndjson(&["--manifest", manifest], &[]);
+    assert!(
+        lines[0].contains(
+            "{\"kind\":\"remote\",\"expected\":\"git@example.com:team/repo.git\",\
+             \"actual\":\"git@example.com:me/repo.git\"}"
+        ),
+        "{}",
+        lines[0]
+    );
+    assert!(
+        lines[0].contains("{\"kind\":\"default_branch\",\"expected\":\"trunk\",\"actual\":null}"),
+        "{}",
+        lines[0]
+    );
+    let text = run(&["--manifest", manifest], &[]);
+    assert!(
+        text.contains("origin is git@example.com:me/repo.git, not git@example.com:team/repo.git")
+            && text.contains("no branch trunk"),
+        "{}",
+        text
+    );
+}
+
+#[test]
+fn unlisted_repositories_are_not_failures() {
+    let listed = Fixture::new("unlisted/listed");
+    listed.write("README", "hello\n");
+    listed.commit("First");
+    let other = Fixture::new("unlisted/other");
+    other.write("README", "hello\n");
+    other.commit("First");
+    let root = listed.dir.parent().unwrap();
+    let manifest = root.join("manifest.toml");
+    std::fs::write(&manifest, "[[repo]]\npath = \"listed\"\n").unwrap();
+    let (root, manifest) = (root.to_str().unwrap(), manifest.to_str().unwrap());
+
+    let out = output(&["--manifest", manifest, "--strict", root], &[]);
+    let text = String::from_utf8_lossy(&out.stdout);
+    assert_eq!(out.status.code(), Some(0), "{}", text);
+    assert_eq!(text, "Not in the manifest\nother\n");
+    let lines = ndjson(&["--manifest", manifest, root], &[]);
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].contains("\"name\":\"listed\""), "{}", lines[0]);
+    assert!(
+        lines[1].ends_with("\"name\":\"other\",\"unlisted\":true}"),
+        "{}",
+        lines[1]
+    );
+    let summary = run(
+        &[
+            "--manifest",
+            manifest,
+            "--summary-only",
+            "--format",
+            "json",
+            root,
+        ],
+        &[],
+    );
+    assert!(
+        summary.contains("\"repos\":1") && summary.contains("\"errors\":0"),
+        "{}",
+        summary
+    );
+    assert!(summary.contains("\"unlisted\":1"), "{}", summary);
+    let check = output(&["check", "--manifest", manifest, root], &[]);
+    assert_eq!(check.status.code(), Some(0));
+}
+
+#[test]
+fn errors_are_recognised_in_any_language() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("broken");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join(".git"), "gitdir: nowhere\n").unwrap();
+    let env = [
+        ("LC_ALL", Path::new("C.UTF-8")),
+        ("LANGUAGE", Path::new("de")),
+    ];
+    let lines = ndjson(&[dir.to_str().unwrap()], &env);
+    assert_eq!(lines.len(), 1);
+    assert!(
+        lines[0].contains("\"kind\":\"not_a_repository\""),
+        "{}",
+        lines[0]
+    );
+}
+
+#[test]
+fn a_closed_pipe_is_not_an_error() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_git-branchstat"))
+        .args(["completions", "zsh"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    drop(child.stdout.take());
+    let out = child.wait_with_output().unwrap();
+    assert!(out.status.success(), "{:?}", out.status);
+    assert_eq!(String::from_utf8_lossy(&out.stderr), "");
+}
+
+#[test]
+fn paths_that_cannot_be_scanned() {
+    let tmp = Path::new(env!("CARGO_TARGET_TMPDIR"));
+    let file = tmp.join("a-file");
+    std::fs::write(&file, "").unwrap();
+    let gone = tmp.join("does-not-exist");
+    let lines = ndjson(&[gone.to_str().unwrap(), file.to_str().unwrap()], &[]);
+    assert_eq!(lines.len(), 2);
+    // In order of path
+    let kinds = ["\"kind\":\"not_a_directory\"", "\"kind\":\"not_found\""];
+    for (line, kind) in lines.iter().zip(&kinds) {
+        assert!(line.contains(kind), "{}", line);
+    }
+    let text = run(&[gone.to_str().unwrap()], &[]);
+    assert!(text.ends_with("error  does not exist\n"), "{}", text);
+}